@@ -0,0 +1,203 @@
+use super::Enricher;
+use crate::model::{Record, MICROJOULES_PER_KILOWATT_HOUR};
+use chrono::{DateTime, TimeZone, Utc};
+use std::path::Path;
+use std::sync::Arc;
+
+/// Carbon intensity of the electricity, in grams of CO2 equivalent per kWh.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Intensity {
+    Static(f64),
+    /// Values starting at the given times, ordered by time.
+    Series(Vec<(DateTime<Utc>, f64)>),
+}
+
+fn parse_time(value: &str) -> Option<DateTime<Utc>> {
+    let value = value.trim();
+    DateTime::parse_from_rfc3339(value)
+        .map(|ts| ts.with_timezone(&Utc))
+        .ok()
+        .or_else(|| {
+            value
+                .parse::<i64>()
+                .ok()
+                .and_then(|secs| Utc.timestamp_opt(secs, 0).single())
+        })
+}
+
+impl Intensity {
+    /// Reads a time series from a JSON file, with a list of objects having a
+    /// `timestamp` and an `intensity`, or from a CSV file, with the timestamp
+    /// and the intensity on each line. The timestamps are either in RFC 3339 or
+    /// in seconds.
+    pub fn from_file(path: &Path) -> Result<Self, String> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|err| format!("couldn't read {:?}: {:?}", path, err))?;
+        let mut values = match path.extension().and_then(|ext| ext.to_str()) {
+            Some("json") => Self::parse_json(&content)?,
+            _ => Self::parse_csv(&content)?,
+        };
+        if values.is_empty() {
+            return Err(format!("no carbon intensity found in {:?}", path));
+        }
+        values.sort_by_key(|(ts, _)| *ts);
+        Ok(Self::Series(values))
+    }
+
+    fn parse_csv(content: &str) -> Result<Vec<(DateTime<Utc>, f64)>, String> {
+        content
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .filter_map(|(index, line)| {
+                let mut cells = line.splitn(2, ',');
+                let entry = cells
+                    .next()
+                    .and_then(parse_time)
+                    .zip(cells.next().and_then(|value| value.trim().parse().ok()));
+                match entry {
+                    Some(entry) => Some(Ok(entry)),
+                    // the first line can be a header
+                    None if index == 0 => None,
+                    None => Some(Err(format!(
+                        "invalid carbon intensity on line {}",
+                        index + 1
+                    ))),
+                }
+            })
+            .collect()
+    }
+
+    fn parse_json(content: &str) -> Result<Vec<(DateTime<Utc>, f64)>, String> {
+        let items: Vec<serde_json::Value> = serde_json::from_str(content)
+            .map_err(|err| format!("invalid carbon intensity file: {:?}", err))?;
+        items
+            .iter()
+            .enumerate()
+            .map(|(index, item)| {
+                let ts = match item.get("timestamp") {
+                    Some(serde_json::Value::String(value)) => parse_time(value),
+                    Some(serde_json::Value::Number(value)) => value
+                        .as_i64()
+                        .and_then(|secs| Utc.timestamp_opt(secs, 0).single()),
+                    _ => None,
+                };
+                let intensity = item.get("intensity").and_then(|value| value.as_f64());
+                ts.zip(intensity)
+                    .ok_or_else(|| format!("invalid carbon intensity at index {}", index))
+            })
+            .collect()
+    }
+
+    /// Intensity at the given time, missing before the first value of a series.
+    pub fn at(&self, ts: DateTime<Utc>) -> Option<f64> {
+        match self {
+            Self::Static(value) => Some(*value),
+            Self::Series(values) => {
+                let index = values.partition_point(|(start, _)| *start <= ts);
+                index.checked_sub(1).map(|index| values[index].1)
+            }
+        }
+    }
+}
+
+/// Converts the energy attributed to the container into emissions, according to
+/// the carbon intensity at the time of the record and the power usage
+/// effectiveness of the data center.
+pub struct CarbonEnricher {
+    intensity: Arc<Intensity>,
+    pue: f64,
+    /// Emissions since the container started being watched, in grams.
+    total: f64,
+}
+
+impl CarbonEnricher {
+    pub fn new(intensity: Arc<Intensity>, pue: f64) -> Self {
+        Self {
+            intensity,
+            pue,
+            total: 0.0,
+        }
+    }
+}
+
+impl Enricher for CarbonEnricher {
+    fn enrich(&mut self, mut record: Record) -> Record {
+        let energy = record.attributed_energy();
+        record.co2_grams = energy
+            .zip(self.intensity.at(record.ts))
+            .map(|(energy, intensity)| {
+                energy / MICROJOULES_PER_KILOWATT_HOUR * intensity * self.pue
+            });
+        self.total += record.co2_grams.unwrap_or_default();
+        record.co2_grams_total = Some(self.total);
+        record
+    }
+
+    fn finish(&mut self, record: Record) -> Record {
+        self.enrich(record)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CarbonEnricher, Intensity};
+    use crate::enrichment::Enricher;
+    use crate::model::Record;
+    use chrono::{TimeZone, Utc};
+    use std::sync::Arc;
+
+    #[test]
+    fn should_parse_time_series() {
+        let csv = "timestamp,intensity\n1970-01-01T00:00:00Z,100\n\n3600,50.5\n";
+        let values = Intensity::parse_csv(csv).unwrap();
+        assert_eq!(
+            values,
+            vec![
+                (Utc.timestamp_opt(0, 0).unwrap(), 100.0),
+                (Utc.timestamp_opt(3600, 0).unwrap(), 50.5)
+            ]
+        );
+        assert!(Intensity::parse_csv("0,100\nwhatever\n").is_err());
+
+        let json = r#"[{"timestamp": "1970-01-01T01:00:00Z", "intensity": 50.5}, {"timestamp": 0, "intensity": 100}]"#;
+        let mut other = Intensity::parse_json(json).unwrap();
+        other.sort_by_key(|(ts, _)| *ts);
+        assert_eq!(other, values);
+        assert!(Intensity::parse_json(r#"[{"intensity": 100}]"#).is_err());
+    }
+
+    #[test]
+    fn should_find_the_intensity_in_time() {
+        let series = Intensity::Series(vec![
+            (Utc.timestamp_opt(1000, 0).unwrap(), 100.0),
+            (Utc.timestamp_opt(2000, 0).unwrap(), 50.0),
+        ]);
+        assert_eq!(series.at(Utc.timestamp_opt(999, 0).unwrap()), None);
+        assert_eq!(series.at(Utc.timestamp_opt(1000, 0).unwrap()), Some(100.0));
+        assert_eq!(series.at(Utc.timestamp_opt(1234, 0).unwrap()), Some(100.0));
+        assert_eq!(series.at(Utc.timestamp_opt(5000, 0).unwrap()), Some(50.0));
+        assert_eq!(
+            Intensity::Static(12.0).at(Utc.timestamp_opt(0, 0).unwrap()),
+            Some(12.0)
+        );
+    }
+
+    #[test]
+    fn should_compute_emissions() {
+        let mut enricher = CarbonEnricher::new(Arc::new(Intensity::Static(200.0)), 1.5);
+        let mut record = Record::random();
+        // 0.1 kWh
+        record.cpu_energy = Some(3.6e11);
+        record.dram_energy = None;
+        let record = enricher.enrich(record);
+        assert_eq!(record.co2_grams, Some(30.0));
+        let record = enricher.enrich(record);
+        assert_eq!(record.co2_grams_total, Some(60.0));
+        let mut record = Record::random();
+        record.cpu_energy = None;
+        let record = enricher.enrich(record);
+        assert_eq!(record.co2_grams, None);
+        assert_eq!(record.co2_grams_total, Some(60.0));
+    }
+}
@@ -0,0 +1,130 @@
+use super::Enricher;
+use crate::model::{Record, MICROJOULES_PER_JOULE};
+
+const BYTES_PER_GIGABYTE: f64 = 1e9;
+
+/// Energy of the resources that aren't measured by RAPL, per gigabyte.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct EnergyCoefficients {
+    /// Power of the resident memory, in watts per gigabyte.
+    pub memory: Option<f64>,
+    /// Energy of the block I/O, in joules per gigabyte read or written.
+    pub io: Option<f64>,
+    /// Energy of the network, in joules per gigabyte received or sent.
+    pub network: Option<f64>,
+}
+
+impl EnergyCoefficients {
+    pub fn is_empty(&self) -> bool {
+        self.memory.is_none() && self.io.is_none() && self.network.is_none()
+    }
+}
+
+/// Bytes transferred since the container started, to compute those of each sample.
+#[derive(Debug, Clone, Copy)]
+struct Transfers {
+    io: Option<u64>,
+    network: Option<u64>,
+}
+
+impl Transfers {
+    fn of(record: &Record) -> Self {
+        Self {
+            io: record
+                .block_io
+                .map(|block| block.read_bytes + block.write_bytes),
+            network: record
+                .network
+                .map(|network| network.rx_bytes + network.tx_bytes),
+        }
+    }
+}
+
+fn energy(coefficient: Option<f64>, current: Option<u64>, previous: Option<u64>) -> Option<f64> {
+    let bytes = current?.checked_sub(previous?)?;
+    coefficient.map(|joules| joules * bytes as f64 / BYTES_PER_GIGABYTE * MICROJOULES_PER_JOULE)
+}
+
+/// Estimates the energy of the memory, the disks and the network of the container
+/// since the previous sample, with the configured coefficients.
+pub struct CoefficientsEnricher {
+    coefficients: EnergyCoefficients,
+    previous: Option<Transfers>,
+}
+
+impl CoefficientsEnricher {
+    pub fn new(coefficients: EnergyCoefficients) -> Self {
+        Self {
+            coefficients,
+            previous: None,
+        }
+    }
+}
+
+impl Enricher for CoefficientsEnricher {
+    fn enrich(&mut self, mut record: Record) -> Record {
+        let memory = record.memory_working_set.or(record.memory_usage);
+        record.memory_energy = self
+            .coefficients
+            .memory
+            .zip(memory)
+            .zip(record.sample_interval)
+            .map(|((watts, memory), interval)| {
+                watts * memory as f64 / BYTES_PER_GIGABYTE * interval * MICROJOULES_PER_JOULE
+            });
+        let current = Transfers::of(&record);
+        if let Some(previous) = self.previous {
+            record.io_energy = energy(self.coefficients.io, current.io, previous.io);
+            record.network_energy =
+                energy(self.coefficients.network, current.network, previous.network);
+        }
+        self.previous = Some(current);
+        record
+    }
+
+    fn reset(&mut self) {
+        self.previous = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CoefficientsEnricher, EnergyCoefficients};
+    use crate::enrichment::Enricher;
+    use crate::model::{BlockUsage, Record};
+
+    #[test]
+    fn should_estimate_the_energy_of_the_resources() {
+        let mut enricher = CoefficientsEnricher::new(EnergyCoefficients {
+            memory: Some(0.5),
+            io: Some(2.0),
+            network: None,
+        });
+        let mut record = Record::random();
+        record.memory_working_set = Some(2_000_000_000);
+        record.sample_interval = Some(2.0);
+        record.block_io = Some(BlockUsage {
+            read_bytes: 1_000_000_000,
+            write_bytes: 0,
+            read_ops: None,
+            write_ops: None,
+        });
+        let first = enricher.enrich(record.clone());
+        assert_eq!(first.memory_energy, Some(2_000_000.0));
+        // the transfers of the first sample are unknown
+        assert_eq!(first.io_energy, None);
+
+        record.block_io = Some(BlockUsage {
+            read_bytes: 1_250_000_000,
+            write_bytes: 250_000_000,
+            read_ops: None,
+            write_ops: None,
+        });
+        let second = enricher.enrich(record.clone());
+        assert_eq!(second.io_energy, Some(1_000_000.0));
+        assert_eq!(second.network_energy, None);
+
+        enricher.reset();
+        assert_eq!(enricher.enrich(record).io_energy, None);
+    }
+}
@@ -0,0 +1,239 @@
+use super::Enricher;
+use crate::model::{Record, MICROJOULES_PER_KILOWATT_HOUR};
+use chrono::{Datelike, Local, NaiveDateTime, Timelike, Weekday};
+use serde::Deserialize;
+use std::convert::TryFrom;
+use std::path::Path;
+use std::sync::Arc;
+
+const MINUTES_PER_DAY: u32 = 24 * 60;
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+struct TariffFile {
+    currency: String,
+    rate: f64,
+    #[serde(default)]
+    windows: Vec<WindowFile>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+struct WindowFile {
+    #[serde(default)]
+    days: Vec<String>,
+    start: String,
+    end: String,
+    rate: f64,
+}
+
+/// Reads a time of the day like `07:30`, in minutes, up to `24:00`.
+fn parse_minutes(value: &str) -> Result<u32, String> {
+    let (hours, minutes) = value
+        .split_once(':')
+        .ok_or_else(|| format!("invalid time {:?}, expected HH:MM", value))?;
+    let total = match (hours.parse::<u32>(), minutes.parse::<u32>()) {
+        (Ok(hours), Ok(minutes)) if minutes < 60 => hours
+            .checked_mul(60)
+            .and_then(|hours| hours.checked_add(minutes)),
+        _ => None,
+    };
+    total
+        .filter(|total| *total <= MINUTES_PER_DAY)
+        .ok_or_else(|| format!("invalid time {:?}, expected HH:MM", value))
+}
+
+/// Period of the week with its own rate.
+#[derive(Debug, Clone, PartialEq)]
+struct Window {
+    /// Days of the window, every day when empty.
+    days: Vec<Weekday>,
+    /// Start of the window in minutes since midnight, included.
+    start: u32,
+    /// End of the window in minutes since midnight, excluded.
+    end: u32,
+    rate: f64,
+}
+
+impl TryFrom<WindowFile> for Window {
+    type Error = String;
+
+    fn try_from(value: WindowFile) -> Result<Self, Self::Error> {
+        let days = value
+            .days
+            .iter()
+            .map(|day| {
+                day.parse::<Weekday>()
+                    .map_err(|_| format!("invalid day {:?}", day))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let start = parse_minutes(&value.start)?;
+        let end = parse_minutes(&value.end)?;
+        if start >= end {
+            return Err(format!(
+                "the window from {} to {} must end after it starts",
+                value.start, value.end
+            ));
+        }
+        Ok(Self {
+            days,
+            start,
+            end,
+            rate: value.rate,
+        })
+    }
+}
+
+impl Window {
+    fn contains(&self, weekday: Weekday, minutes: u32) -> bool {
+        (self.days.is_empty() || self.days.contains(&weekday))
+            && self.start <= minutes
+            && minutes < self.end
+    }
+}
+
+/// Price of the electricity, per kWh, with a flat rate applying outside of the
+/// time-of-use windows.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tariff {
+    currency: String,
+    rate: f64,
+    windows: Vec<Window>,
+}
+
+impl Tariff {
+    /// Reads a tariff from a JSON file, like
+    /// `{"currency": "EUR", "rate": 0.15, "windows": [{"days": ["sat", "sun"], "start": "00:00", "end": "24:00", "rate": 0.1}]}`.
+    pub fn from_file(path: &Path) -> Result<Self, String> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|err| format!("couldn't read {:?}: {:?}", path, err))?;
+        Self::parse(&content)
+    }
+
+    fn parse(content: &str) -> Result<Self, String> {
+        let file: TariffFile = serde_json::from_str(content)
+            .map_err(|err| format!("invalid tariff file: {:?}", err))?;
+        Ok(Self {
+            currency: file.currency,
+            rate: file.rate,
+            windows: file
+                .windows
+                .into_iter()
+                .map(Window::try_from)
+                .collect::<Result<Vec<_>, _>>()?,
+        })
+    }
+
+    /// Rate at the given local time, from the first window containing it.
+    fn rate_at(&self, ts: NaiveDateTime) -> f64 {
+        let minutes = ts.hour() * 60 + ts.minute();
+        self.windows
+            .iter()
+            .find(|window| window.contains(ts.weekday(), minutes))
+            .map(|window| window.rate)
+            .unwrap_or(self.rate)
+    }
+}
+
+/// Converts the energy attributed to the container into its cost, according to
+/// the tariff at the local time of the record.
+pub struct CostEnricher {
+    tariff: Arc<Tariff>,
+    /// Cost since the container started being watched.
+    total: f64,
+}
+
+impl CostEnricher {
+    pub fn new(tariff: Arc<Tariff>) -> Self {
+        Self { tariff, total: 0.0 }
+    }
+}
+
+impl Enricher for CostEnricher {
+    fn enrich(&mut self, mut record: Record) -> Record {
+        let energy = record.attributed_energy();
+        let rate = self
+            .tariff
+            .rate_at(record.ts.with_timezone(&Local).naive_local());
+        record.cost = energy.map(|energy| energy / MICROJOULES_PER_KILOWATT_HOUR * rate);
+        self.total += record.cost.unwrap_or_default();
+        record.cost_total = Some(self.total);
+        record.cost_currency = Some(self.tariff.currency.clone());
+        record
+    }
+
+    fn finish(&mut self, record: Record) -> Record {
+        self.enrich(record)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_minutes, CostEnricher, Tariff};
+    use crate::enrichment::Enricher;
+    use crate::model::Record;
+    use chrono::NaiveDate;
+    use std::sync::Arc;
+
+    const TARIFF: &str = r#"{
+        "currency": "EUR",
+        "rate": 0.2,
+        "windows": [
+            {"days": ["sat", "sunday"], "start": "00:00", "end": "24:00", "rate": 0.1},
+            {"start": "22:00", "end": "24:00", "rate": 0.15},
+            {"start": "00:00", "end": "06:30", "rate": 0.15}
+        ]
+    }"#;
+
+    #[test]
+    fn should_parse_tariff() {
+        let tariff = Tariff::parse(TARIFF).unwrap();
+        assert_eq!(tariff.currency, "EUR");
+        assert_eq!(tariff.windows.len(), 3);
+        assert_eq!(tariff.windows[2].end, 390);
+        assert_eq!(parse_minutes("24:00"), Ok(1440));
+        assert!(parse_minutes("24:01").is_err());
+        assert!(parse_minutes("7h").is_err());
+        assert!(parse_minutes("99999999:00").is_err());
+        assert!(Tariff::parse(r#"{"currency": "EUR", "rate": 0.2, "windows": [{"start": "10:00", "end": "09:00", "rate": 1}]}"#).is_err());
+        assert!(Tariff::parse(r#"{"currency": "EUR", "rate": 0.2, "windows": [{"days": ["someday"], "start": "10:00", "end": "11:00", "rate": 1}]}"#).is_err());
+        assert!(Tariff::parse(r#"{"currency": "EUR", "rate": 0.2, "windows": [{"start": "99999999:00", "end": "24:00", "rate": 1}]}"#).is_err());
+        assert!(Tariff::parse(r#"{"rate": 0.2}"#).is_err());
+    }
+
+    #[test]
+    fn should_find_the_rate_in_time() {
+        let tariff = Tariff::parse(TARIFF).unwrap();
+        // 2022-05-02 is a monday
+        let at = |day: u32, hour: u32, minute: u32| {
+            tariff.rate_at(
+                NaiveDate::from_ymd_opt(2022, 5, day)
+                    .and_then(|date| date.and_hms_opt(hour, minute, 0))
+                    .unwrap(),
+            )
+        };
+        assert_eq!(at(2, 12, 0), 0.2);
+        assert_eq!(at(2, 6, 29), 0.15);
+        assert_eq!(at(2, 6, 30), 0.2);
+        assert_eq!(at(2, 23, 59), 0.15);
+        assert_eq!(at(7, 12, 0), 0.1);
+        assert_eq!(at(8, 23, 0), 0.1);
+    }
+
+    #[test]
+    fn should_compute_cost() {
+        let flat = Tariff::parse(r#"{"currency": "USD", "rate": 0.25}"#).unwrap();
+        let mut enricher = CostEnricher::new(Arc::new(flat));
+        let mut record = Record::random();
+        // 2 kWh
+        record.cpu_energy = Some(7.2e12);
+        record.dram_energy = None;
+        let record = enricher.enrich(record);
+        assert_eq!(record.cost, Some(0.5));
+        assert_eq!(record.cost_currency.as_deref(), Some("USD"));
+        let mut summary = Record::summary("id".into(), "name".into(), record.ts);
+        summary.dram_energy = Some(3.6e12);
+        let summary = enricher.finish(summary);
+        assert_eq!(summary.cost_total, Some(0.75));
+    }
+}
@@ -0,0 +1,133 @@
+use crate::model::MICROJOULES_PER_JOULE;
+use std::path::Path;
+
+/// Power of the host according to the utilization of its cpu, used to estimate
+/// its energy when it can't be measured.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PowerModel {
+    /// Power growing linearly from idle to full utilization, in watts.
+    Linear { idle: f64, max: f64 },
+    /// Power at some utilizations, from 0 to 1, in watts, ordered by utilization.
+    Curve(Vec<(f64, f64)>),
+}
+
+impl PowerModel {
+    /// Reads a curve from a CSV file, with the utilization in percent and the
+    /// power in watts on each line, like `50,120.5`.
+    pub fn from_file(path: &Path) -> Result<Self, String> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|err| format!("couldn't read {:?}: {:?}", path, err))?;
+        Self::parse_curve(&content)
+    }
+
+    /// Reads the points of a curve, which must be sorted by utilization, from 0
+    /// to 100 percent.
+    fn parse_curve(content: &str) -> Result<Self, String> {
+        let points = content
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .filter_map(|(index, line)| {
+                let point = line.split_once(',').and_then(|(utilization, watts)| {
+                    let utilization = utilization.trim().parse::<f64>().ok()?;
+                    let watts = watts.trim().parse::<f64>().ok()?;
+                    Some((utilization / 100.0, watts))
+                });
+                match point {
+                    Some(point) => Some(Ok(point)),
+                    // the first line can be a header
+                    None if index == 0 => None,
+                    None => Some(Err(format!("invalid power on line {}", index + 1))),
+                }
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        if points.is_empty() {
+            return Err("no power found in the curve".into());
+        }
+        if let Some((utilization, _)) = points
+            .iter()
+            .find(|(utilization, _)| !(0.0..=1.0).contains(utilization))
+        {
+            return Err(format!(
+                "the utilization {} is outside of 0 to 100 percent",
+                utilization * 100.0
+            ));
+        }
+        if let Some(pair) = points.windows(2).find(|pair| pair[0].0 >= pair[1].0) {
+            return Err(format!(
+                "the utilizations must be sorted, but {} comes after {}",
+                pair[1].0 * 100.0,
+                pair[0].0 * 100.0
+            ));
+        }
+        Ok(Self::Curve(points))
+    }
+
+    /// Power at the given utilization of the cpu, from 0 to 1, interpolated
+    /// between the points of a curve and constant outside of them.
+    pub fn power(&self, utilization: f64) -> f64 {
+        let utilization = utilization.clamp(0.0, 1.0);
+        match self {
+            Self::Linear { idle, max } => idle + (max - idle) * utilization,
+            Self::Curve(points) => {
+                let index = points.partition_point(|(point, _)| *point <= utilization);
+                match (
+                    index.checked_sub(1).map(|index| points[index]),
+                    points.get(index).copied(),
+                ) {
+                    (Some((start, low)), Some((end, high))) => {
+                        low + (high - low) * (utilization - start) / (end - start)
+                    }
+                    (Some((_, watts)), None) | (None, Some((_, watts))) => watts,
+                    (None, None) => 0.0,
+                }
+            }
+        }
+    }
+
+    /// Energy over a duration in seconds at the given utilization, in micro joules.
+    pub fn energy(&self, utilization: f64, duration: f64) -> f64 {
+        self.power(utilization) * duration * MICROJOULES_PER_JOULE
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PowerModel;
+
+    #[test]
+    fn should_parse_curve() {
+        let model =
+            PowerModel::parse_curve("utilization,watts\n0,50\n\n50,150\n100,200\n").unwrap();
+        assert_eq!(
+            model,
+            PowerModel::Curve(vec![(0.0, 50.0), (0.5, 150.0), (1.0, 200.0)])
+        );
+        assert!(PowerModel::parse_curve("utilization,watts\n").is_err());
+        assert!(PowerModel::parse_curve("0,50\nwhatever\n").is_err());
+        // unsorted, duplicated or out of range utilizations
+        assert!(PowerModel::parse_curve("100,200\n0,50\n").is_err());
+        assert!(PowerModel::parse_curve("0,50\n0,60\n").is_err());
+        assert!(PowerModel::parse_curve("0,50\n150,200\n").is_err());
+        assert!(PowerModel::parse_curve("-10,40\n0,50\n").is_err());
+    }
+
+    #[test]
+    fn should_estimate_power() {
+        let linear = PowerModel::Linear {
+            idle: 10.0,
+            max: 30.0,
+        };
+        assert_eq!(linear.power(0.0), 10.0);
+        assert_eq!(linear.power(0.25), 15.0);
+        assert_eq!(linear.power(2.0), 30.0);
+        assert_eq!(linear.energy(0.5, 2.0), 40_000_000.0);
+
+        let curve = PowerModel::Curve(vec![(0.25, 50.0), (0.5, 150.0), (1.0, 200.0)]);
+        assert_eq!(curve.power(0.0), 50.0);
+        assert_eq!(curve.power(0.375), 100.0);
+        assert_eq!(curve.power(0.5), 150.0);
+        assert_eq!(curve.power(0.75), 175.0);
+        assert_eq!(curve.power(1.0), 200.0);
+    }
+}
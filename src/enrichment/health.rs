@@ -0,0 +1,128 @@
+use super::Enricher;
+use crate::model::{ContainerHealth, Record};
+use bollard::models::{ContainerInspectResponse, EventMessage};
+use std::sync::Arc;
+
+const HEALTH_STATUS_ACTION: &str = "health_status";
+
+impl From<&ContainerInspectResponse> for ContainerHealth {
+    fn from(item: &ContainerInspectResponse) -> Self {
+        let state = item.state.as_ref();
+        let health = state.and_then(|state| state.health.as_ref());
+        Self {
+            status: health
+                .and_then(|health| health.status)
+                .map(|status| status.to_string())
+                .filter(|status| !status.is_empty()),
+            failing_streak: health
+                .and_then(|health| health.failing_streak)
+                .map(|streak| streak.max(0) as u64),
+            restart_count: item.restart_count.map(|count| count.max(0) as u64),
+            oom_killed: state.and_then(|state| state.oom_killed),
+        }
+    }
+}
+
+/// Attaches the health check state, the restart count and the OOM killed flag of
+/// the container to the records.
+///
+/// The state is read when the container is inspected, which happens again after
+/// each health status change, restart or OOM kill, instead of at every sample.
+#[derive(Default)]
+pub struct HealthEnricher {
+    health: Option<Arc<ContainerHealth>>,
+}
+
+impl Enricher for HealthEnricher {
+    fn enrich(&mut self, mut record: Record) -> Record {
+        record.health = self.health.clone();
+        record
+    }
+
+    fn finish(&mut self, record: Record) -> Record {
+        self.enrich(record)
+    }
+
+    fn needs_inspect(&self) -> bool {
+        true
+    }
+
+    fn inspect(&mut self, container: &ContainerInspectResponse) {
+        self.health = Some(Arc::new(ContainerHealth::from(container)));
+    }
+
+    fn notify(&mut self, event: &EventMessage) -> bool {
+        let action = event.action.as_deref().unwrap_or_default();
+        // the health events are like `health_status: healthy`
+        if let Some(status) = action
+            .strip_prefix(HEALTH_STATUS_ACTION)
+            .and_then(|rest| rest.strip_prefix(':'))
+        {
+            let mut health = self.health.as_deref().cloned().unwrap_or_default();
+            health.status = Some(status.trim().to_string());
+            self.health = Some(Arc::new(health));
+            return true;
+        }
+        match action {
+            "oom" => {
+                let mut health = self.health.as_deref().cloned().unwrap_or_default();
+                health.oom_killed = Some(true);
+                self.health = Some(Arc::new(health));
+                true
+            }
+            "restart" | "start" => true,
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::HealthEnricher;
+    use crate::enrichment::Enricher;
+    use crate::model::Record;
+    use bollard::models::{
+        ContainerInspectResponse, ContainerState, EventMessage, Health, HealthStatusEnum,
+    };
+
+    fn event(action: &str) -> EventMessage {
+        EventMessage {
+            action: Some(action.into()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn should_attach_inspected_health() {
+        let mut enricher = HealthEnricher::default();
+        enricher.inspect(&ContainerInspectResponse {
+            restart_count: Some(3),
+            state: Some(ContainerState {
+                oom_killed: Some(false),
+                health: Some(Health {
+                    status: Some(HealthStatusEnum::UNHEALTHY),
+                    failing_streak: Some(2),
+                    log: None,
+                }),
+                ..Default::default()
+            }),
+            ..Default::default()
+        });
+        let health = enricher.enrich(Record::random()).health.unwrap();
+        assert_eq!(health.status.as_deref(), Some("unhealthy"));
+        assert_eq!(health.failing_streak, Some(2));
+        assert_eq!(health.restart_count, Some(3));
+        assert_eq!(health.oom_killed, Some(false));
+    }
+
+    #[test]
+    fn should_follow_health_events() {
+        let mut enricher = HealthEnricher::default();
+        assert!(enricher.notify(&event("health_status: healthy")));
+        assert!(enricher.notify(&event("oom")));
+        assert!(!enricher.notify(&event("exec_start: sh")));
+        let health = enricher.enrich(Record::random()).health.unwrap();
+        assert_eq!(health.status.as_deref(), Some("healthy"));
+        assert_eq!(health.oom_killed, Some(true));
+    }
+}
@@ -0,0 +1,118 @@
+use super::Enricher;
+use crate::model::{ContainerMetadata, Record};
+use bollard::models::ContainerInspectResponse;
+use chrono::{DateTime, Utc};
+use std::sync::Arc;
+
+/// Parses the timestamps of the docker api, that uses year one when not set.
+fn parse_timestamp(value: Option<&String>) -> Option<DateTime<Utc>> {
+    value
+        .and_then(|value| DateTime::parse_from_rfc3339(value).ok())
+        .map(|value| value.with_timezone(&Utc))
+        .filter(|value| value.timestamp() > 0)
+}
+
+impl From<&ContainerInspectResponse> for ContainerMetadata {
+    fn from(item: &ContainerInspectResponse) -> Self {
+        let config = item.config.as_ref();
+        Self {
+            image: config.and_then(|config| config.image.clone()),
+            image_id: item.image.clone(),
+            labels: config
+                .and_then(|config| config.labels.as_ref())
+                .map(|labels| labels.clone().into_iter().collect())
+                .unwrap_or_default(),
+            hostname: config.and_then(|config| config.hostname.clone()),
+            created_at: parse_timestamp(item.created.as_ref()),
+            started_at: parse_timestamp(
+                item.state
+                    .as_ref()
+                    .and_then(|state| state.started_at.as_ref()),
+            ),
+            restart_policy: item
+                .host_config
+                .as_ref()
+                .and_then(|host| host.restart_policy.as_ref())
+                .and_then(|policy| policy.name)
+                .map(|name| name.to_string())
+                .filter(|name| !name.is_empty()),
+        }
+    }
+}
+
+/// Attaches the details of the container, from the last inspection, to the records.
+#[derive(Default)]
+pub struct MetadataEnricher {
+    metadata: Option<Arc<ContainerMetadata>>,
+}
+
+impl Enricher for MetadataEnricher {
+    fn enrich(&mut self, mut record: Record) -> Record {
+        record.metadata = self.metadata.clone();
+        record
+    }
+
+    fn finish(&mut self, record: Record) -> Record {
+        self.enrich(record)
+    }
+
+    fn needs_inspect(&self) -> bool {
+        true
+    }
+
+    fn inspect(&mut self, container: &ContainerInspectResponse) {
+        self.metadata = Some(Arc::new(ContainerMetadata::from(container)));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::MetadataEnricher;
+    use crate::enrichment::Enricher;
+    use crate::model::Record;
+    use bollard::models::{
+        ContainerConfig, ContainerInspectResponse, ContainerState, HostConfig, RestartPolicy,
+        RestartPolicyNameEnum,
+    };
+    use std::collections::HashMap;
+
+    #[test]
+    fn should_attach_inspected_metadata() {
+        let mut labels = HashMap::new();
+        labels.insert("com.docker.compose.service".to_string(), "api".to_string());
+        let container = ContainerInspectResponse {
+            image: Some("sha256:abcd".into()),
+            created: Some("2022-05-01T10:00:00.123456789Z".into()),
+            config: Some(ContainerConfig {
+                image: Some("nginx:latest".into()),
+                hostname: Some("f00".into()),
+                labels: Some(labels),
+                ..Default::default()
+            }),
+            state: Some(ContainerState {
+                started_at: Some("0001-01-01T00:00:00Z".into()),
+                ..Default::default()
+            }),
+            host_config: Some(HostConfig {
+                restart_policy: Some(RestartPolicy {
+                    name: Some(RestartPolicyNameEnum::UNLESS_STOPPED),
+                    maximum_retry_count: None,
+                }),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let mut enricher = MetadataEnricher::default();
+        assert!(enricher.enrich(Record::random()).metadata.is_none());
+        enricher.inspect(&container);
+        let record = enricher.enrich(Record::random());
+        let metadata = record.metadata.unwrap();
+        assert_eq!(metadata.image.as_deref(), Some("nginx:latest"));
+        assert_eq!(metadata.image_id.as_deref(), Some("sha256:abcd"));
+        assert_eq!(metadata.compose_service(), Some("api"));
+        assert_eq!(metadata.compose_project(), None);
+        assert_eq!(metadata.created_at.unwrap().timestamp(), 1651399200);
+        assert_eq!(metadata.started_at, None);
+        assert_eq!(metadata.restart_policy.as_deref(), Some("unless-stopped"));
+    }
+}
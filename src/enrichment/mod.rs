@@ -1,8 +1,24 @@
+#[cfg(feature = "enrichment-carbon")]
+mod carbon;
 #[cfg(feature = "enrichment-powercap")]
-mod powercap;
+mod coefficients;
+#[cfg(feature = "enrichment-cost")]
+mod cost;
+#[cfg(feature = "enrichment-powercap")]
+mod estimation;
+mod health;
+mod metadata;
+#[cfg(feature = "enrichment-powercap")]
+pub mod powercap;
+#[cfg(feature = "enrichment-sensors")]
+pub mod sensors;
+
+#[cfg(feature = "enrichment-powercap")]
+pub use powercap::{EnergyLedger, EnergySource, StaticPolicy};
 
 use crate::model::Record;
 use crate::Params;
+use bollard::models::{ContainerInspectResponse, EventMessage};
 #[cfg(feature = "enrichment-powercap")]
 use std::sync::Arc;
 
@@ -12,6 +28,26 @@ pub trait Enricher: Send {
     }
 
     fn reset(&mut self) {}
+
+    /// Completes the summary record sent when the container stops being watched.
+    fn finish(&mut self, record: Record) -> Record {
+        record
+    }
+
+    /// Whether the enricher needs the details of the container.
+    fn needs_inspect(&self) -> bool {
+        false
+    }
+
+    /// Receives the details of the container, when it starts being watched and
+    /// each time it gets renamed.
+    fn inspect(&mut self, _container: &ContainerInspectResponse) {}
+
+    /// Receives the docker events of the container and returns whether the
+    /// container should be inspected again.
+    fn notify(&mut self, _event: &EventMessage) -> bool {
+        false
+    }
 }
 
 pub struct EnrichmentStack(Vec<Box<dyn Enricher>>);
@@ -24,12 +60,52 @@ impl Enricher for EnrichmentStack {
     fn reset(&mut self) {
         self.0.iter_mut().for_each(|next| next.reset())
     }
+
+    fn finish(&mut self, record: Record) -> Record {
+        self.0.iter_mut().fold(record, |r, next| next.finish(r))
+    }
+
+    fn needs_inspect(&self) -> bool {
+        self.0.iter().any(|next| next.needs_inspect())
+    }
+
+    fn inspect(&mut self, container: &ContainerInspectResponse) {
+        self.0.iter_mut().for_each(|next| next.inspect(container))
+    }
+
+    fn notify(&mut self, event: &EventMessage) -> bool {
+        // every enricher must receive the event
+        let mut result = false;
+        for next in self.0.iter_mut() {
+            result |= next.notify(event);
+        }
+        result
+    }
 }
 
 #[derive(Clone)]
 pub struct EnrichmentBuilder {
+    health: bool,
+    metadata: bool,
     #[cfg(feature = "enrichment-powercap")]
-    powercap: Option<Arc<::powercap::PowerCap>>,
+    energy: Option<EnergySource>,
+    #[cfg(feature = "enrichment-powercap")]
+    static_policy: Option<StaticPolicy>,
+    /// Power of the host when idle, in watts, given by the power model.
+    #[cfg(feature = "enrichment-powercap")]
+    idle_power: Option<f64>,
+    #[cfg(feature = "enrichment-sensors")]
+    sensor: Option<sensors::PowerSensor>,
+    #[cfg(feature = "enrichment-powercap")]
+    ledger: EnergyLedger,
+    #[cfg(feature = "enrichment-powercap")]
+    coefficients: coefficients::EnergyCoefficients,
+    #[cfg(feature = "enrichment-carbon")]
+    carbon: Option<Arc<carbon::Intensity>>,
+    #[cfg(feature = "enrichment-carbon")]
+    pue: f64,
+    #[cfg(feature = "enrichment-cost")]
+    tariff: Option<Arc<cost::Tariff>>,
 }
 
 impl Params {
@@ -48,26 +124,142 @@ impl Params {
         }
     }
 
+    #[cfg(feature = "enrichment-powercap")]
+    fn create_power_model(&self) -> Option<estimation::PowerModel> {
+        match (self.idle_watts, self.max_watts, &self.power_curve_file) {
+            (Some(idle), Some(max), _) => Some(estimation::PowerModel::Linear { idle, max }),
+            (_, _, Some(path)) => Some(
+                estimation::PowerModel::from_file(path)
+                    .expect("couldn't load the power curve file"),
+            ),
+            _ => None,
+        }
+    }
+
+    /// Measures the energy with RAPL, or with the power sensor, or estimates it
+    /// with the power model when RAPL isn't available.
+    #[cfg(feature = "enrichment-powercap")]
+    fn create_energy_source(
+        &self,
+        model: Option<Arc<estimation::PowerModel>>,
+    ) -> Option<EnergySource> {
+        match self.create_powercap() {
+            Some(powercap) => Some(EnergySource::Rapl(Arc::new(powercap))),
+            #[cfg(feature = "enrichment-sensors")]
+            None if self.power_sensor.is_some() => {
+                tracing::info!("measuring the energy with the power sensor");
+                Some(EnergySource::Sensor)
+            }
+            None => model.map(|model| {
+                tracing::info!("estimating the energy with the power model");
+                EnergySource::Model(model)
+            }),
+        }
+    }
+
+    #[cfg(feature = "enrichment-carbon")]
+    fn create_carbon_intensity(&self) -> Option<carbon::Intensity> {
+        match (self.carbon_intensity, &self.carbon_intensity_file) {
+            (Some(value), _) => Some(carbon::Intensity::Static(value)),
+            (None, Some(path)) => Some(
+                carbon::Intensity::from_file(path)
+                    .expect("couldn't load the carbon intensity file"),
+            ),
+            (None, None) => None,
+        }
+    }
+
     pub fn enrichment_builder(&self) -> EnrichmentBuilder {
+        #[cfg(feature = "enrichment-powercap")]
+        let model = self.create_power_model().map(Arc::new);
         EnrichmentBuilder {
+            health: !self.disable_health,
+            metadata: !self.disable_metadata,
+            #[cfg(feature = "enrichment-powercap")]
+            energy: self.create_energy_source(model.clone()),
+            #[cfg(feature = "enrichment-powercap")]
+            static_policy: self.static_power_policy,
+            #[cfg(feature = "enrichment-powercap")]
+            idle_power: model.map(|model| model.power(0.0)),
+            #[cfg(feature = "enrichment-sensors")]
+            sensor: self.power_sensor.clone(),
+            #[cfg(feature = "enrichment-powercap")]
+            ledger: EnergyLedger::default(),
             #[cfg(feature = "enrichment-powercap")]
-            powercap: self.create_powercap().map(Arc::new),
+            coefficients: coefficients::EnergyCoefficients {
+                memory: self.memory_watts_per_gb,
+                io: self.io_joules_per_gb,
+                network: self.network_joules_per_gb,
+            },
+            #[cfg(feature = "enrichment-carbon")]
+            carbon: self.create_carbon_intensity().map(Arc::new),
+            #[cfg(feature = "enrichment-carbon")]
+            pue: self.pue,
+            #[cfg(feature = "enrichment-cost")]
+            tariff: self.tariff_file.as_ref().map(|path| {
+                Arc::new(cost::Tariff::from_file(path).expect("couldn't load the tariff file"))
+            }),
         }
     }
 }
 
 impl EnrichmentBuilder {
-    #[cfg(not(any(feature = "enrichment-powercap")))]
-    pub fn build(&self) -> EnrichmentStack {
-        EnrichmentStack(Vec::new())
+    /// Source of the host energy, with the ledger of the energy attributed to
+    /// the containers.
+    #[cfg(feature = "enrichment-powercap")]
+    pub fn energy(&self) -> Option<(EnergySource, EnergyLedger)> {
+        self.energy
+            .clone()
+            .map(|source| (source, self.ledger.clone()))
+    }
+
+    /// Policy splitting the static energy of the host, with the idle power of the
+    /// host when it's known from the power model.
+    #[cfg(feature = "enrichment-powercap")]
+    pub fn static_power(&self) -> Option<(StaticPolicy, Option<f64>)> {
+        self.static_policy.map(|policy| (policy, self.idle_power))
+    }
+
+    /// Sensor of the host power, either measuring the energy or cross-checking RAPL.
+    #[cfg(feature = "enrichment-sensors")]
+    pub fn sensor(&self) -> Option<sensors::PowerSensor> {
+        self.sensor.clone()
     }
 
-    #[cfg(any(feature = "enrichment-powercap"))]
     pub fn build(&self) -> EnrichmentStack {
         let mut result: Vec<Box<dyn Enricher>> = Vec::new();
+        if self.metadata {
+            result.push(Box::new(metadata::MetadataEnricher::default()));
+        }
+        if self.health {
+            result.push(Box::new(health::HealthEnricher::default()));
+        }
+        #[cfg(feature = "enrichment-powercap")]
+        // the energy is read by the host sampler, which shares the ledger
+        if let Some(ref source) = self.energy {
+            result.push(Box::new(powercap::PowerCapEnricher::new(
+                self.ledger.clone(),
+                source.method(),
+                self.static_policy.unwrap_or_default(),
+            )));
+        }
         #[cfg(feature = "enrichment-powercap")]
-        if let Some(pcap) = self.powercap.as_ref() {
-            result.push(Box::new(powercap::PowerCapEnricher::from(pcap.clone())));
+        if !self.coefficients.is_empty() {
+            result.push(Box::new(coefficients::CoefficientsEnricher::new(
+                self.coefficients,
+            )));
+        }
+        #[cfg(feature = "enrichment-carbon")]
+        // after the energy is attributed to the container and estimated
+        if let Some(ref intensity) = self.carbon {
+            result.push(Box::new(carbon::CarbonEnricher::new(
+                intensity.clone(),
+                self.pue,
+            )));
+        }
+        #[cfg(feature = "enrichment-cost")]
+        if let Some(ref tariff) = self.tariff {
+            result.push(Box::new(cost::CostEnricher::new(tariff.clone())));
         }
         EnrichmentStack(result)
     }
@@ -1,32 +1,789 @@
+use super::estimation::PowerModel;
 use super::Enricher;
-use crate::model::Record;
+use crate::model::{EnergyAnomaly, EnergyMethod, EnergyZone, Record, MICROJOULES_PER_JOULE};
+use bollard::models::ContainerInspectResponse;
 use powercap::PowerCap;
-use std::sync::Arc;
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
+const POWERCAP_PATH: &str = "/sys/class/powercap";
+/// Highest plausible power of a zone whose range of power is unknown, in micro
+/// watts, above which a change of its counter is taken for a reset.
+const MAX_ZONE_POWER: f64 = 2_000_000_000.0;
+
+/// Where the energy of the host comes from.
+#[derive(Clone)]
+pub enum EnergySource {
+    Rapl(Arc<PowerCap>),
+    /// Power sensor outside of RAPL, when RAPL isn't available.
+    #[cfg(feature = "enrichment-sensors")]
+    Sensor,
+    /// Estimation from the utilization of the cpu, when RAPL isn't available.
+    Model(Arc<PowerModel>),
+}
+
+impl EnergySource {
+    pub fn method(&self) -> EnergyMethod {
+        match self {
+            Self::Rapl(_) => EnergyMethod::Measured,
+            #[cfg(feature = "enrichment-sensors")]
+            Self::Sensor => EnergyMethod::Measured,
+            Self::Model(_) => EnergyMethod::Estimated,
+        }
+    }
+}
+
+/// How the static energy of the host, consumed even when it's idle, is split
+/// between the containers.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum StaticPolicy {
+    /// Left to the host.
+    #[default]
+    None,
+    /// Equally between the running containers.
+    Equal,
+    /// By share of the memory reservation of the running containers, or of
+    /// their memory usage when they have no reservation.
+    Memory,
+    /// By share of the cpu time of the running containers.
+    Cpu,
+}
+
+impl FromStr for StaticPolicy {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        match input.to_lowercase().as_str() {
+            "none" => Ok(Self::None),
+            "equal" => Ok(Self::Equal),
+            "memory" => Ok(Self::Memory),
+            "cpu" => Ok(Self::Cpu),
+            _ => Err(format!("unknown static power policy {:?}", input)),
+        }
+    }
+}
+
+/// Energy measured on the host during a tick of the host sampler.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct HostEnergy {
+    /// Energy of the processor packages, in micro joules.
+    pub package: Option<f64>,
+    /// Energy of the memory, in micro joules.
+    pub dram: Option<f64>,
+    pub anomaly: Option<EnergyAnomaly>,
+    /// Duration of the tick, in seconds.
+    pub duration: f64,
+    /// Cpu time used on the host during the tick, in seconds.
+    pub busy_time: f64,
+    /// Memory of the host, in bytes.
+    pub memory_total: Option<u64>,
+    /// Part of the energy of the packages consumed even when the host is idle,
+    /// in micro joules, split according to the policy. Without it, the whole
+    /// energy of the packages is split by cpu time.
+    pub static_energy: Option<f64>,
+    pub policy: StaticPolicy,
+}
+
+/// Energy attributed to a container by the host sampler, in micro joules.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Credit {
+    pub cpu: Option<f64>,
+    /// Parts of the energy of the cpu consumed by the activity of the container,
+    /// and consumed by the host even when idle, when they are told apart.
+    pub cpu_dynamic: Option<f64>,
+    pub cpu_static: Option<f64>,
+    pub dram: Option<f64>,
+    pub anomaly: Option<EnergyAnomaly>,
+    /// Duration of the ticks the energy was measured over, in seconds.
+    pub duration: f64,
+}
+
+impl Credit {
+    fn merge(self, other: Self) -> Self {
+        let add = |first: Option<f64>, second: Option<f64>| match (first, second) {
+            (Some(first), Some(second)) => Some(first + second),
+            (first, second) => first.or(second),
+        };
+        Self {
+            cpu: add(self.cpu, other.cpu),
+            cpu_dynamic: add(self.cpu_dynamic, other.cpu_dynamic),
+            cpu_static: add(self.cpu_static, other.cpu_static),
+            dram: add(self.dram, other.dram),
+            // a discarded sample matters more than a corrected one
+            anomaly: self.anomaly.max(other.anomaly),
+            duration: self.duration + other.duration,
+        }
+    }
+
+    /// Average power of the cpu over the ticks, in watts.
+    pub fn cpu_power(&self) -> Option<f64> {
+        average_power(self.cpu, self.duration)
+    }
+}
+
+/// Average power, in watts, of an energy in micro joules over a duration in seconds.
+pub fn average_power(energy: Option<f64>, duration: f64) -> Option<f64> {
+    energy
+        .filter(|_| duration > 0.0)
+        .map(|energy| energy / MICROJOULES_PER_JOULE / duration)
+}
+
+/// Usage of a container since the previous tick of the host sampler.
+#[derive(Debug, Default)]
+struct Usage {
+    /// Cpu time, in seconds.
+    cpu_time: f64,
+    /// Last known memory usage, in bytes.
+    memory: Option<u64>,
+    /// Memory reservation, in bytes.
+    memory_reservation: Option<u64>,
+}
+
+impl Usage {
+    /// Weight of the container in the split of the static energy.
+    fn static_weight(&self, policy: StaticPolicy) -> f64 {
+        match policy {
+            StaticPolicy::None => 0.0,
+            StaticPolicy::Equal => 1.0,
+            // not the limit, as docker gives the memory of the host to the
+            // containers without limit
+            StaticPolicy::Memory => {
+                self.memory_reservation.or(self.memory).unwrap_or_default() as f64
+            }
+            StaticPolicy::Cpu => self.cpu_time,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct LedgerState {
+    usages: HashMap<String, Usage>,
+    credits: HashMap<String, Credit>,
+}
+
+/// Ledger shared between the watchers, reporting the usage of their container,
+/// and the host sampler, splitting the energy measured at each tick between the
+/// containers according to their usage over the same window.
+#[derive(Clone, Default)]
+pub struct EnergyLedger(Arc<Mutex<LedgerState>>);
+
+impl EnergyLedger {
+    /// Records the usage of a container since its previous sample.
+    pub fn report(
+        &self,
+        container_id: &str,
+        cpu_time: Option<f64>,
+        memory: Option<u64>,
+        memory_reservation: Option<u64>,
+    ) {
+        if let Ok(mut state) = self.0.lock() {
+            let usage = state.usages.entry(container_id.to_string()).or_default();
+            usage.cpu_time += cpu_time.unwrap_or_default();
+            usage.memory = memory.or(usage.memory);
+            usage.memory_reservation = memory_reservation.or(usage.memory_reservation);
+        }
+    }
+
+    /// Returns the energy attributed to the container since the previous call.
+    pub fn take(&self, container_id: &str) -> Credit {
+        self.0
+            .lock()
+            .ok()
+            .and_then(|mut state| state.credits.remove(container_id))
+            .unwrap_or_default()
+    }
+
+    /// Drops what's known about a container that is not watched anymore.
+    pub fn forget(&self, container_id: &str) {
+        if let Ok(mut state) = self.0.lock() {
+            state.usages.remove(container_id);
+            state.credits.remove(container_id);
+        }
+    }
+
+    /// Splits the energy measured by the host sampler between the containers
+    /// that reported some usage since the previous tick, and returns the total
+    /// attributed energy. The energy of the packages is split by cpu time, over
+    /// the busy time of the host, and the energy of the memory by share of the
+    /// memory of the host, so that what's left goes to the host itself. When the
+    /// static energy is known, only the dynamic energy is split by cpu time, and
+    /// the static energy is split according to the policy.
+    pub fn attribute(&self, host: &HostEnergy) -> f64 {
+        let mut state = match self.0.lock() {
+            Ok(state) => state,
+            Err(_) => return 0.0,
+        };
+        let usages = std::mem::take(&mut state.usages);
+        let cpu_total = usages
+            .values()
+            .map(|usage| usage.cpu_time)
+            .sum::<f64>()
+            .max(host.busy_time);
+        let memory_total = usages
+            .values()
+            .filter_map(|usage| usage.memory)
+            .sum::<u64>()
+            .max(host.memory_total.unwrap_or_default());
+        let static_total: f64 = usages
+            .values()
+            .map(|usage| usage.static_weight(host.policy))
+            .sum();
+        let static_energy = host.package.and(host.static_energy);
+        let mut attributed = 0.0;
+        for (container_id, usage) in usages {
+            let dynamic = host.package.map(|energy| match cpu_total > 0.0 {
+                true => (energy - static_energy.unwrap_or_default()) * usage.cpu_time / cpu_total,
+                false => 0.0,
+            });
+            let static_share = static_energy.map(|energy| match static_total > 0.0 {
+                true => energy * usage.static_weight(host.policy) / static_total,
+                false => 0.0,
+            });
+            let credit = Credit {
+                cpu: dynamic.map(|dynamic| dynamic + static_share.unwrap_or_default()),
+                cpu_dynamic: static_share.and(dynamic),
+                cpu_static: static_share,
+                dram: host
+                    .dram
+                    .zip(usage.memory)
+                    .filter(|_| memory_total > 0)
+                    .map(|(energy, memory)| energy * memory as f64 / memory_total as f64),
+                anomaly: host.anomaly,
+                duration: host.duration,
+            };
+            attributed += credit.cpu.unwrap_or_default() + credit.dram.unwrap_or_default();
+            let previous = state.credits.remove(&container_id).unwrap_or_default();
+            state.credits.insert(container_id, previous.merge(credit));
+        }
+        attributed
+    }
+}
+
+/// Energy counter of a RAPL zone, in micro joules, named like `package-0`, or
+/// like `package-0/dram` for a subzone.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ZoneCounter {
+    pub name: String,
+    pub energy: u64,
+    /// Highest value of the counter, which wraps to zero after it.
+    pub max_energy_range: Option<u64>,
+    /// Highest power the zone can draw, in micro watts.
+    pub max_power_range: Option<u64>,
+}
+
+impl ZoneCounter {
+    /// Energy consumed since the previous value of the counter, read the given
+    /// time before, correcting a wraparound, or the anomaly preventing to
+    /// compute it.
+    fn delta(
+        &self,
+        previous: &Self,
+        elapsed: Duration,
+    ) -> Result<(u64, Option<EnergyAnomaly>), EnergyAnomaly> {
+        let delta = match (
+            self.energy.checked_sub(previous.energy),
+            self.max_energy_range,
+        ) {
+            (Some(delta), _) => (delta, None),
+            // the counter goes up to its range included, and takes one more
+            // micro joule to get back to zero
+            (None, Some(max)) if previous.energy <= max => (
+                (max - previous.energy)
+                    .checked_add(self.energy)
+                    .and_then(|delta| delta.checked_add(1))
+                    .ok_or(EnergyAnomaly::Discarded)?,
+                Some(EnergyAnomaly::Wrapped),
+            ),
+            // without range, a wraparound can't be told apart from a reset
+            (None, _) => return Err(EnergyAnomaly::Discarded),
+        };
+        if matches!(self.max_energy_range, Some(max) if delta.0 > max) {
+            // the counter can't move by more than its range between two reads
+            // without being misread
+            return Err(EnergyAnomaly::Discarded);
+        }
+        // a reset, or a counter going backwards, looks like a wraparound
+        // consuming most of the range at once
+        let max_power = self
+            .max_power_range
+            .map_or(MAX_ZONE_POWER, |power| power as f64);
+        match delta.0 as f64 > max_power * elapsed.as_secs_f64() {
+            true => Err(EnergyAnomaly::Discarded),
+            false => Ok(delta),
+        }
+    }
+}
+
+/// Reads the highest power of a zone, which isn't exposed by the powercap crate.
+fn read_max_power_range(zone: &str) -> Option<u64> {
+    std::fs::read_to_string(format!("{}/{}/max_power_range_uw", POWERCAP_PATH, zone))
+        .ok()?
+        .trim()
+        .parse()
+        .ok()
+}
+
+/// Reads the counters of every zone and subzone, ordered by socket and domain.
+pub fn read_zones(powercap: &PowerCap) -> Vec<ZoneCounter> {
+    let mut sockets: Vec<_> = powercap.intel_rapl.sockets.values().collect();
+    sockets.sort_by_key(|socket| socket.id);
+    let mut result = Vec::new();
+    for socket in sockets {
+        // the powercap crate doesn't expose the name of the zones, like psys
+        let name =
+            std::fs::read_to_string(format!("{}/intel-rapl:{}/name", POWERCAP_PATH, socket.id))
+                .map(|name| name.trim().to_string())
+                .unwrap_or_else(|_| format!("package-{}", socket.id));
+        let zone = format!("intel-rapl:{}", socket.id);
+        if let Ok(energy) = socket.energy() {
+            result.push(ZoneCounter {
+                name: name.clone(),
+                energy,
+                max_energy_range: socket.max_energy_range().ok(),
+                max_power_range: read_max_power_range(&zone),
+            });
+        }
+        let mut domains: Vec<_> = socket.domains.values().collect();
+        domains.sort_by_key(|domain| domain.id);
+        for domain in domains {
+            let domain_name = domain.name().unwrap_or_else(|_| domain.id.to_string());
+            if let Ok(energy) = domain.energy() {
+                result.push(ZoneCounter {
+                    name: format!("{}/{}", name, domain_name),
+                    energy,
+                    max_energy_range: domain.max_energy_range().ok(),
+                    max_power_range: read_max_power_range(&format!(
+                        "{}/{}:{}",
+                        zone, zone, domain.id
+                    )),
+                });
+            }
+        }
+    }
+    result
+}
+
+/// Energy consumed by each zone between two reads of the counters.
+#[derive(Debug, Default, PartialEq)]
+pub struct ZoneDeltas {
+    /// Energy of each zone, empty when the sample is discarded.
+    pub zones: Vec<EnergyZone>,
+    pub anomaly: Option<EnergyAnomaly>,
+}
+
+/// Energy consumed by each zone between the previous and the current counters,
+/// read the given time apart. When a counter can't be trusted, the energy of
+/// every zone is discarded, so that the sample isn't under reported.
+pub fn zone_deltas(
+    previous: &[ZoneCounter],
+    current: &[ZoneCounter],
+    elapsed: Duration,
+) -> ZoneDeltas {
+    let mut result = ZoneDeltas::default();
+    for zone in current {
+        let last = match previous.iter().find(|last| last.name == zone.name) {
+            Some(last) => last,
+            None => continue,
+        };
+        match zone.delta(last, elapsed) {
+            Ok((energy, anomaly)) => {
+                result.anomaly = result.anomaly.or(anomaly);
+                result.zones.push(EnergyZone {
+                    name: zone.name.clone(),
+                    energy: energy as f64,
+                });
+            }
+            Err(anomaly) => {
+                tracing::debug!("discarding energy sample because of zone {:?}", zone.name);
+                return ZoneDeltas {
+                    zones: Vec::new(),
+                    anomaly: Some(anomaly),
+                };
+            }
+        }
+    }
+    result
+}
+
+/// Energy of the processor packages, which include their core and uncore subzones.
+/// The platform zone, `psys`, also includes the packages and is left aside.
+pub fn package_energy(zones: &[EnergyZone]) -> Option<f64> {
+    sum_energy(zones, |name| {
+        !name.contains('/') && name != "psys" && name != "dram"
+    })
+}
+
+/// Energy of the memory, exposed as a subzone of the packages or as a zone.
+pub fn dram_energy(zones: &[EnergyZone]) -> Option<f64> {
+    sum_energy(zones, |name| name == "dram" || name.ends_with("/dram"))
+}
+
+fn sum_energy<F: Fn(&str) -> bool>(zones: &[EnergyZone], filter: F) -> Option<f64> {
+    zones
+        .iter()
+        .filter(|zone| filter(&zone.name))
+        .map(|zone| zone.energy)
+        .fold(None, |total, energy| {
+            Some(total.unwrap_or_default() + energy)
+        })
+}
+
+/// Reports the usage of the container to the ledger and attaches the energy the
+/// host sampler attributed to it.
 pub struct PowerCapEnricher {
-    inner: Arc<PowerCap>,
-    last: Option<u64>,
+    ledger: EnergyLedger,
+    method: EnergyMethod,
+    policy: StaticPolicy,
+    /// Memory reservation of the container, from the last inspection, in bytes.
+    memory_reservation: Option<u64>,
+    /// Energy of the cpu since the container started being watched, in micro joules.
+    cpu_energy_total: f64,
 }
 
-impl From<Arc<PowerCap>> for PowerCapEnricher {
-    fn from(inner: Arc<PowerCap>) -> Self {
-        Self { inner, last: None }
+impl PowerCapEnricher {
+    pub fn new(ledger: EnergyLedger, method: EnergyMethod, policy: StaticPolicy) -> Self {
+        Self {
+            ledger,
+            method,
+            policy,
+            memory_reservation: None,
+            cpu_energy_total: 0.0,
+        }
+    }
+
+    fn credit(&mut self, record: &mut Record) {
+        let credit = self.ledger.take(&record.container_id);
+        self.cpu_energy_total += credit.cpu.unwrap_or_default();
+        record.cpu_energy = credit.cpu;
+        record.cpu_energy_total = Some(self.cpu_energy_total);
+        record.power_watts = credit.cpu_power();
+        record.dram_energy = credit.dram;
+        record.energy_anomaly = credit.anomaly;
+        record.energy_method = Some(self.method);
+        record.dynamic_energy = credit.cpu_dynamic;
+        record.static_energy = credit.cpu_static;
     }
 }
 
 impl Enricher for PowerCapEnricher {
-    fn enrich(&mut self, record: Record) -> Record {
-        let current = self.inner.intel_rapl.total_energy().ok();
-        let result = if let (Some(last), Some(now)) = (self.last, current) {
-            record.with_energy(Some(now - last))
-        } else {
-            record
+    fn enrich(&mut self, mut record: Record) -> Record {
+        self.ledger.report(
+            &record.container_id,
+            record.cpu_time.map(|time| time as f64 / 1_000_000_000.0),
+            record.memory_working_set.or(record.memory_usage),
+            self.memory_reservation,
+        );
+        self.credit(&mut record);
+        record
+    }
+
+    fn finish(&mut self, mut record: Record) -> Record {
+        // the energy attributed since the last sample is only part of the total
+        self.credit(&mut record);
+        record.power_watts = None;
+        self.ledger.forget(&record.container_id);
+        record
+    }
+
+    fn needs_inspect(&self) -> bool {
+        self.policy == StaticPolicy::Memory
+    }
+
+    fn inspect(&mut self, container: &ContainerInspectResponse) {
+        self.memory_reservation = container
+            .host_config
+            .as_ref()
+            .and_then(|config| config.memory_reservation)
+            .filter(|reservation| *reservation > 0)
+            .map(|reservation| reservation as u64);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        dram_energy, package_energy, zone_deltas, Credit, EnergyLedger, HostEnergy,
+        PowerCapEnricher, StaticPolicy, ZoneCounter,
+    };
+    use crate::enrichment::Enricher;
+    use crate::model::{EnergyAnomaly, EnergyMethod, Record};
+    use bollard::models::{ContainerInspectResponse, HostConfig};
+    use std::time::Duration;
+
+    const SECOND: Duration = Duration::from_secs(1);
+
+    fn counters(values: &[(&str, u64)]) -> Vec<ZoneCounter> {
+        values
+            .iter()
+            .map(|(name, energy)| ZoneCounter {
+                name: name.to_string(),
+                energy: *energy,
+                max_energy_range: Some(10_000),
+                max_power_range: None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn ledger_should_conserve_energy() {
+        let ledger = EnergyLedger::default();
+        let other = ledger.clone();
+        ledger.report("first", Some(1.5), Some(100), None);
+        other.report("second", Some(0.5), Some(300), None);
+        // the host was busy during 4 seconds, half of it outside of the containers
+        let host = HostEnergy {
+            package: Some(800.0),
+            dram: Some(100.0),
+            anomaly: None,
+            duration: 2.0,
+            busy_time: 4.0,
+            memory_total: Some(1000),
+            static_energy: None,
+            policy: StaticPolicy::default(),
+        };
+        let attributed = ledger.attribute(&host);
+        assert_eq!(attributed, 300.0 + 100.0 + 10.0 + 30.0);
+        let first = ledger.take("first");
+        assert_eq!(first.cpu, Some(300.0));
+        assert_eq!(first.dram, Some(10.0));
+        assert_eq!(first.anomaly, None);
+        assert_eq!(first.cpu_power(), Some(300.0 / 1_000_000.0 / 2.0));
+        assert_eq!(ledger.take("first"), Credit::default());
+        // without usage reported since the previous tick, nothing is attributed
+        let attributed = ledger.attribute(&host);
+        assert_eq!(attributed, 0.0);
+        let second = other.take("second");
+        assert_eq!(second.cpu, Some(100.0));
+        assert_eq!(second.duration, 2.0);
+    }
+
+    #[test]
+    fn ledger_should_split_everything_between_busy_containers() {
+        let ledger = EnergyLedger::default();
+        ledger.report("first", Some(3.0), None, None);
+        ledger.report("second", Some(1.0), None, None);
+        ledger.report("second", Some(1.0), None, None);
+        // the busy time of the host is measured over a slightly different window
+        let attributed = ledger.attribute(&HostEnergy {
+            package: Some(500.0),
+            anomaly: Some(EnergyAnomaly::Wrapped),
+            busy_time: 4.5,
+            ..Default::default()
+        });
+        assert_eq!(attributed, 500.0);
+        let second = ledger.take("second");
+        assert_eq!(second.cpu, Some(200.0));
+        assert_eq!(second.dram, None);
+        assert_eq!(second.anomaly, Some(EnergyAnomaly::Wrapped));
+        ledger.forget("first");
+        assert_eq!(ledger.take("first"), Credit::default());
+    }
+
+    #[test]
+    fn ledger_should_split_the_static_energy_by_policy() {
+        let split = |policy: StaticPolicy| {
+            let ledger = EnergyLedger::default();
+            ledger.report("first", Some(1.0), Some(50), Some(300));
+            ledger.report("second", Some(3.0), Some(150), Some(100));
+            let attributed = ledger.attribute(&HostEnergy {
+                package: Some(1000.0),
+                duration: 1.0,
+                busy_time: 8.0,
+                static_energy: Some(200.0),
+                policy,
+                ..Default::default()
+            });
+            (attributed, ledger.take("first"), ledger.take("second"))
+        };
+        // the dynamic energy is split by cpu time over the busy time of the host
+        let (attributed, first, second) = split(StaticPolicy::None);
+        assert_eq!(attributed, 400.0);
+        assert_eq!(first.cpu_dynamic, Some(100.0));
+        assert_eq!(first.cpu_static, Some(0.0));
+        assert_eq!(second.cpu, Some(300.0));
+        let (attributed, first, second) = split(StaticPolicy::Equal);
+        assert_eq!(attributed, 600.0);
+        assert_eq!(first.cpu, Some(200.0));
+        assert_eq!(second.cpu_static, Some(100.0));
+        let (_, first, second) = split(StaticPolicy::Memory);
+        assert_eq!(first.cpu_static, Some(150.0));
+        assert_eq!(second.cpu_static, Some(50.0));
+        let (_, first, second) = split(StaticPolicy::Cpu);
+        assert_eq!(first.cpu_static, Some(50.0));
+        assert_eq!(second.cpu, Some(450.0));
+        // without static energy, the whole energy is split by cpu time
+        let ledger = EnergyLedger::default();
+        ledger.report("first", Some(1.0), None, Some(300));
+        ledger.attribute(&HostEnergy {
+            package: Some(1000.0),
+            busy_time: 8.0,
+            policy: StaticPolicy::Equal,
+            ..Default::default()
+        });
+        let first = ledger.take("first");
+        assert_eq!(first.cpu, Some(125.0));
+        assert_eq!(first.cpu_static, None);
+        assert_eq!(first.cpu_dynamic, None);
+    }
+
+    #[test]
+    fn ledger_should_split_the_static_energy_by_memory_usage_without_reservation() {
+        let ledger = EnergyLedger::default();
+        ledger.report("first", Some(1.0), Some(100), None);
+        ledger.report("second", Some(1.0), Some(300), None);
+        ledger.report("third", Some(1.0), Some(300), Some(600));
+        ledger.attribute(&HostEnergy {
+            package: Some(1000.0),
+            duration: 1.0,
+            busy_time: 10.0,
+            static_energy: Some(500.0),
+            policy: StaticPolicy::Memory,
+            ..Default::default()
+        });
+        assert_eq!(ledger.take("first").cpu_static, Some(50.0));
+        assert_eq!(ledger.take("second").cpu_static, Some(150.0));
+        assert_eq!(ledger.take("third").cpu_static, Some(300.0));
+    }
+
+    #[test]
+    fn enricher_should_report_the_inspected_memory_reservation() {
+        let ledger = EnergyLedger::default();
+        let mut enricher =
+            PowerCapEnricher::new(ledger.clone(), EnergyMethod::Measured, StaticPolicy::Memory);
+        assert!(enricher.needs_inspect());
+        enricher.inspect(&ContainerInspectResponse {
+            host_config: Some(HostConfig {
+                memory_reservation: Some(0),
+                ..Default::default()
+            }),
+            ..Default::default()
+        });
+        assert_eq!(enricher.memory_reservation, None);
+        enricher.inspect(&ContainerInspectResponse {
+            host_config: Some(HostConfig {
+                memory_reservation: Some(2048),
+                ..Default::default()
+            }),
+            ..Default::default()
+        });
+        assert_eq!(enricher.memory_reservation, Some(2048));
+        let other = PowerCapEnricher::new(ledger, EnergyMethod::Measured, StaticPolicy::Cpu);
+        assert!(!other.needs_inspect());
+    }
+
+    #[test]
+    fn enricher_should_sum_the_energy_until_the_summary() {
+        let ledger = EnergyLedger::default();
+        let mut enricher =
+            PowerCapEnricher::new(ledger.clone(), EnergyMethod::Estimated, StaticPolicy::None);
+        let host = HostEnergy {
+            package: Some(4_000_000.0),
+            duration: 2.0,
+            busy_time: 0.0,
+            ..Default::default()
         };
-        self.last = current;
-        result
+        let record = enricher.enrich(Record::random());
+        assert_eq!(record.cpu_energy, None);
+        assert_eq!(record.cpu_energy_total, Some(0.0));
+        // the container is the only one using the cpu
+        ledger.attribute(&host);
+        let record = enricher.enrich(Record::random());
+        assert_eq!(record.cpu_energy, Some(4_000_000.0));
+        assert_eq!(record.cpu_energy_total, Some(4_000_000.0));
+        assert_eq!(record.power_watts, Some(2.0));
+        assert_eq!(record.energy_method, Some(EnergyMethod::Estimated));
+        ledger.attribute(&host);
+        let summary = enricher.finish(Record::summary("hello".into(), "world".into(), record.ts));
+        assert_eq!(summary.cpu_energy_total, Some(8_000_000.0));
+        assert_eq!(summary.power_watts, None);
+    }
+
+    #[test]
+    fn should_split_energy_by_zone() {
+        let previous = counters(&[
+            ("package-0", 1000),
+            ("package-0/core", 500),
+            ("package-0/dram", 200),
+            ("package-1", 1000),
+            ("psys", 5000),
+        ]);
+        let current = counters(&[
+            ("package-0", 1600),
+            ("package-0/core", 900),
+            ("package-0/dram", 300),
+            ("package-0/uncore", 100),
+            ("package-1", 1400),
+            ("psys", 7000),
+        ]);
+        let deltas = zone_deltas(&previous, &current, SECOND);
+        assert_eq!(deltas.anomaly, None);
+        let zones = deltas.zones;
+        assert_eq!(zones.len(), 5);
+        assert_eq!(zones[1].name, "package-0/core");
+        assert_eq!(zones[1].energy, 400.0);
+        assert_eq!(package_energy(&zones), Some(1000.0));
+        assert_eq!(dram_energy(&zones), Some(100.0));
+        assert_eq!(dram_energy(&zones[..2]), None);
+    }
+
+    #[test]
+    fn should_correct_wraparounds() {
+        let previous = counters(&[("package-0", 9_900), ("package-0/dram", 200)]);
+        let current = counters(&[("package-0", 150), ("package-0/dram", 300)]);
+        let deltas = zone_deltas(&previous, &current, SECOND);
+        assert_eq!(deltas.anomaly, Some(EnergyAnomaly::Wrapped));
+        assert_eq!(package_energy(&deltas.zones), Some(251.0));
+        assert_eq!(dram_energy(&deltas.zones), Some(100.0));
     }
 
-    fn reset(&mut self) {
-        self.last = None;
+    #[test]
+    fn should_discard_implausible_counters() {
+        let previous = counters(&[("package-0", 9_900), ("package-0/dram", 200)]);
+        // counter above its range
+        let current = counters(&[("package-0", 10_000), ("package-0/dram", 20_300)]);
+        let deltas = zone_deltas(&previous, &current, SECOND);
+        assert_eq!(deltas.anomaly, Some(EnergyAnomaly::Discarded));
+        assert!(deltas.zones.is_empty());
+        // reset without known range
+        let mut current = counters(&[("package-0", 100)]);
+        current[0].max_energy_range = None;
+        let deltas = zone_deltas(&previous, &current, SECOND);
+        assert_eq!(deltas.anomaly, Some(EnergyAnomaly::Discarded));
+        assert_eq!(package_energy(&deltas.zones), None);
+    }
+
+    #[test]
+    fn should_discard_resets() {
+        // the range of a package is usually about 262 kJ
+        let counter = |energy: u64, max_power_range: Option<u64>| ZoneCounter {
+            name: "package-0".into(),
+            energy,
+            max_energy_range: Some(262_143_328_850),
+            max_power_range,
+        };
+        let deltas = zone_deltas(
+            &[counter(200_000_000_000, None)],
+            &[counter(1_000, None)],
+            SECOND,
+        );
+        assert_eq!(deltas.anomaly, Some(EnergyAnomaly::Discarded));
+        assert!(deltas.zones.is_empty());
+        // a real wraparound consumes a plausible energy over the time elapsed
+        let deltas = zone_deltas(
+            &[counter(262_000_000_000, None)],
+            &[counter(1_000, None)],
+            SECOND,
+        );
+        assert_eq!(deltas.anomaly, Some(EnergyAnomaly::Wrapped));
+        assert_eq!(package_energy(&deltas.zones), Some(143_329_851.0));
+        // the bound comes from the power range of the zone when it's known
+        let previous = [counter(262_000_000_000, Some(100_000_000))];
+        let current = [counter(1_000, Some(100_000_000))];
+        let deltas = zone_deltas(&previous, &current, SECOND);
+        assert_eq!(deltas.anomaly, Some(EnergyAnomaly::Discarded));
+        let deltas = zone_deltas(&previous, &current, Duration::from_secs(2));
+        assert_eq!(deltas.anomaly, Some(EnergyAnomaly::Wrapped));
     }
 }
@@ -0,0 +1,338 @@
+use crate::model::EnergyAnomaly;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+const HWMON_PATH: &str = "/sys/class/hwmon";
+const POWER_SUPPLY_PATH: &str = "/sys/class/power_supply";
+/// Name of the hwmon chips registered by the ACPI power meter driver.
+const POWER_METER_NAME: &str = "power_meter";
+/// Names of the hwmon chips of the graphic cards, whose power isn't part of the
+/// host processors.
+const GPU_NAMES: &[&str] = &["amdgpu", "i915", "nouveau", "radeon", "xe"];
+
+/// Sensor of the power of the host, outside of RAPL.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PowerSensor {
+    /// Energy or power inputs of the hwmon chips with the given name, like
+    /// `amd_energy`, or of the first chip giving some, leaving aside the graphic
+    /// cards and the power meter.
+    Hwmon(Option<String>),
+    /// Power of the whole system given by an ACPI power meter.
+    PowerMeter,
+    /// Discharge of the batteries, only known when running on battery.
+    PowerSupply,
+}
+
+impl FromStr for PowerSensor {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        match input.to_lowercase().as_str() {
+            "hwmon" => Ok(Self::Hwmon(None)),
+            "power-meter" => Ok(Self::PowerMeter),
+            "power-supply" => Ok(Self::PowerSupply),
+            // the name of the chip is kept as is
+            _ => match input.strip_prefix("hwmon:") {
+                Some(chip) if !chip.is_empty() => Ok(Self::Hwmon(Some(chip.to_string()))),
+                _ => Err(format!("unknown power sensor {:?}", input)),
+            },
+        }
+    }
+}
+
+/// Value read from a sensor.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Reading {
+    /// Energy counter, in micro joules.
+    Energy(u64),
+    /// Instant power, in micro watts.
+    Power(f64),
+}
+
+impl Reading {
+    /// Energy consumed over a duration in seconds since the previous reading, in
+    /// micro joules, missing for the first reading of a counter, or the anomaly
+    /// preventing to compute it.
+    pub fn energy(
+        &self,
+        previous: Option<&Self>,
+        duration: f64,
+    ) -> Result<Option<f64>, EnergyAnomaly> {
+        match (self, previous) {
+            (Self::Energy(current), Some(Self::Energy(previous))) => current
+                .checked_sub(*previous)
+                .map(|delta| Some(delta as f64))
+                .ok_or(EnergyAnomaly::Discarded),
+            (Self::Energy(_), _) => Ok(None),
+            (Self::Power(power), _) => Ok((duration > 0.0).then(|| power * duration)),
+        }
+    }
+}
+
+fn read_value(path: &Path) -> Option<u64> {
+    std::fs::read_to_string(path).ok()?.trim().parse().ok()
+}
+
+fn read_text(path: &Path) -> Option<String> {
+    std::fs::read_to_string(path)
+        .ok()
+        .map(|value| value.trim().to_string())
+}
+
+fn list_dir(path: &Path) -> Vec<PathBuf> {
+    let mut entries: Vec<_> = std::fs::read_dir(path)
+        .map(|entries| entries.flatten().map(|entry| entry.path()).collect())
+        .unwrap_or_default();
+    entries.sort();
+    entries
+}
+
+/// Inputs of a hwmon chip starting with the prefix, like `energy1_input`, with their label.
+fn hwmon_inputs(chip: &Path, prefix: &str, suffix: &str) -> Vec<(Option<String>, u64)> {
+    list_dir(chip)
+        .into_iter()
+        .filter_map(|path| {
+            let name = path.file_name()?.to_str()?;
+            let index = name.strip_prefix(prefix)?.strip_suffix(suffix)?;
+            index.parse::<u32>().ok()?;
+            let label = read_text(&chip.join(format!("{}{}_label", prefix, index)));
+            Some((label, read_value(&path)?))
+        })
+        .collect()
+}
+
+/// Energy of a hwmon chip, leaving aside the inputs of the cores when the chip
+/// also gives those of the sockets, which include them.
+fn chip_energy(chip: &Path) -> Option<u64> {
+    let inputs = hwmon_inputs(chip, "energy", "_input");
+    let is_socket = |label: &Option<String>| {
+        label
+            .as_deref()
+            .map(|label| label.to_lowercase().contains("socket"))
+            .unwrap_or(false)
+    };
+    let has_sockets = inputs.iter().any(|(label, _)| is_socket(label));
+    inputs
+        .into_iter()
+        .filter(|(label, _)| !has_sockets || is_socket(label))
+        .map(|(_, value)| value)
+        .reduce(|total, value| total + value)
+}
+
+/// Power of a hwmon chip, from its instant or average power inputs, leaving
+/// aside the rails when the chip also gives their total, like power supplies.
+fn chip_power(chip: &Path) -> Option<u64> {
+    let mut inputs = hwmon_inputs(chip, "power", "_input");
+    if inputs.is_empty() {
+        inputs = hwmon_inputs(chip, "power", "_average");
+    }
+    let is_total = |label: &Option<String>| {
+        label
+            .as_deref()
+            .map(|label| label.to_lowercase().contains("total"))
+            .unwrap_or(false)
+    };
+    let has_total = inputs.iter().any(|(label, _)| is_total(label));
+    inputs
+        .into_iter()
+        .filter(|(label, _)| !has_total || is_total(label))
+        .map(|(_, value)| value)
+        .reduce(|total, value| total + value)
+}
+
+/// Which hwmon chips are read, by name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ChipFilter<'a> {
+    /// Every chip with the name.
+    Named(&'a str),
+    /// The first chip giving a value, leaving aside those of the graphic cards
+    /// and the power meter, as the chips can measure the same power, like a
+    /// power supply and the rails of the motherboard.
+    Host,
+}
+
+impl ChipFilter<'_> {
+    fn matches(&self, name: Option<&str>) -> bool {
+        match (self, name) {
+            (Self::Named(expected), Some(name)) => *expected == name,
+            (Self::Named(_), None) => false,
+            (Self::Host, Some(name)) => name != POWER_METER_NAME && !GPU_NAMES.contains(&name),
+            (Self::Host, None) => true,
+        }
+    }
+}
+
+fn read_hwmon(root: &Path, filter: ChipFilter) -> Option<Reading> {
+    let chips: Vec<_> = list_dir(root)
+        .into_iter()
+        .filter(|chip| filter.matches(read_text(&chip.join("name")).as_deref()))
+        .collect();
+    let limit = match filter {
+        ChipFilter::Named(_) => chips.len(),
+        ChipFilter::Host => 1,
+    };
+    // energy counters are more precise than instant powers
+    let energy = chips
+        .iter()
+        .filter_map(|chip| chip_energy(chip))
+        .take(limit)
+        .reduce(|total, value| total + value);
+    match energy {
+        Some(energy) => Some(Reading::Energy(energy)),
+        None => chips
+            .iter()
+            .filter_map(|chip| chip_power(chip))
+            .take(limit)
+            .reduce(|total, value| total + value)
+            .map(|power| Reading::Power(power as f64)),
+    }
+}
+
+/// Power drawn from the batteries that are discharging.
+fn read_power_supply(root: &Path) -> Option<Reading> {
+    list_dir(root)
+        .into_iter()
+        .filter(|supply| read_text(&supply.join("type")).as_deref() == Some("Battery"))
+        .filter(|supply| read_text(&supply.join("status")).as_deref() == Some("Discharging"))
+        .filter_map(|supply| {
+            read_value(&supply.join("power_now"))
+                .map(|power| power as f64)
+                .or_else(|| {
+                    // in micro amperes and micro volts
+                    let current = read_value(&supply.join("current_now"))?;
+                    let voltage = read_value(&supply.join("voltage_now"))?;
+                    Some(current as f64 * voltage as f64 / 1_000_000.0)
+                })
+        })
+        .reduce(|total, value| total + value)
+        .map(Reading::Power)
+}
+
+impl PowerSensor {
+    pub fn read(&self) -> Option<Reading> {
+        match self {
+            Self::Hwmon(Some(chip)) => read_hwmon(Path::new(HWMON_PATH), ChipFilter::Named(chip)),
+            Self::Hwmon(None) => read_hwmon(Path::new(HWMON_PATH), ChipFilter::Host),
+            Self::PowerMeter => {
+                read_hwmon(Path::new(HWMON_PATH), ChipFilter::Named(POWER_METER_NAME))
+            }
+            Self::PowerSupply => read_power_supply(Path::new(POWER_SUPPLY_PATH)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{read_hwmon, read_power_supply, ChipFilter, PowerSensor, Reading};
+    use crate::model::EnergyAnomaly;
+    use std::path::{Path, PathBuf};
+    use std::str::FromStr;
+
+    fn tree(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let root = std::env::temp_dir().join(format!("docker-activity-{}", name));
+        let _ = std::fs::remove_dir_all(&root);
+        for (path, content) in files {
+            let path = root.join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, content).unwrap();
+        }
+        root
+    }
+
+    #[test]
+    fn should_read_hwmon() {
+        let root = tree(
+            "hwmon",
+            &[
+                ("hwmon0/name", "amd_energy\n"),
+                ("hwmon0/energy1_input", "100\n"),
+                ("hwmon0/energy1_label", "Ecore000\n"),
+                ("hwmon0/energy2_input", "1000\n"),
+                ("hwmon0/energy2_label", "Esocket0\n"),
+                ("hwmon1/name", "power_meter\n"),
+                ("hwmon1/power1_average", "45000000\n"),
+                ("hwmon2/name", "k10temp\n"),
+                ("hwmon2/temp1_input", "40000\n"),
+            ],
+        );
+        assert_eq!(
+            read_hwmon(&root, ChipFilter::Host),
+            Some(Reading::Energy(1000))
+        );
+        assert_eq!(
+            read_hwmon(&root, ChipFilter::Named("power_meter")),
+            Some(Reading::Power(45_000_000.0))
+        );
+        assert_eq!(read_hwmon(Path::new("/nowhere"), ChipFilter::Host), None);
+    }
+
+    #[test]
+    fn should_read_hwmon_of_the_host_or_named_chip() {
+        let root = tree(
+            "hwmon-chips",
+            &[
+                ("hwmon0/name", "amdgpu\n"),
+                ("hwmon0/power1_average", "30000000\n"),
+                ("hwmon1/name", "nct6775\n"),
+                ("hwmon1/power1_input", "80000000\n"),
+                ("hwmon2/name", "corsairpsu\n"),
+                ("hwmon2/power1_input", "150000000\n"),
+                ("hwmon2/power1_label", "power total\n"),
+                ("hwmon2/power2_input", "50000000\n"),
+                ("hwmon2/power2_label", "power 12v\n"),
+            ],
+        );
+        // the graphic card is left aside, and the chips aren't added up
+        assert_eq!(
+            read_hwmon(&root, ChipFilter::Host),
+            Some(Reading::Power(80_000_000.0))
+        );
+        // the rails are part of the total
+        assert_eq!(
+            read_hwmon(&root, ChipFilter::Named("corsairpsu")),
+            Some(Reading::Power(150_000_000.0))
+        );
+        assert_eq!(read_hwmon(&root, ChipFilter::Named("unknown")), None);
+        assert_eq!(
+            PowerSensor::from_str("hwmon:corsairpsu"),
+            Ok(PowerSensor::Hwmon(Some("corsairpsu".into())))
+        );
+        assert_eq!(PowerSensor::from_str("hwmon"), Ok(PowerSensor::Hwmon(None)));
+        assert!(PowerSensor::from_str("hwmon:").is_err());
+    }
+
+    #[test]
+    fn should_read_power_supply() {
+        let root = tree(
+            "power-supply",
+            &[
+                ("AC/type", "Mains\n"),
+                ("BAT0/type", "Battery\n"),
+                ("BAT0/status", "Discharging\n"),
+                ("BAT0/current_now", "1000000\n"),
+                ("BAT0/voltage_now", "12000000\n"),
+                ("BAT1/type", "Battery\n"),
+                ("BAT1/status", "Charging\n"),
+                ("BAT1/power_now", "5000000\n"),
+            ],
+        );
+        assert_eq!(read_power_supply(&root), Some(Reading::Power(12_000_000.0)));
+    }
+
+    #[test]
+    fn should_compute_energy_between_readings() {
+        let power = Reading::Power(2_000_000.0);
+        assert_eq!(power.energy(None, 1.5), Ok(Some(3_000_000.0)));
+        assert_eq!(power.energy(None, 0.0), Ok(None));
+        let energy = Reading::Energy(1500);
+        assert_eq!(
+            energy.energy(Some(&Reading::Energy(1000)), 1.0),
+            Ok(Some(500.0))
+        );
+        assert_eq!(
+            energy.energy(Some(&Reading::Energy(2000)), 1.0),
+            Err(EnergyAnomaly::Discarded)
+        );
+        assert_eq!(energy.energy(None, 1.0), Ok(None));
+    }
+}
@@ -9,3 +9,12 @@ impl From<std::io::Error> for Error {
         Self::Io(err)
     }
 }
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(err) => write!(f, "io error: {}", err),
+            Self::Custom(msg) => write!(f, "{}", msg),
+        }
+    }
+}
@@ -1,5 +1,7 @@
 use crate::exporter::Exporter;
-use crate::format::{Format, Formatter};
+#[cfg(feature = "formatter-avro")]
+use crate::format::avro::{encode_bytes, encode_long, Codec, Header, Schema, SYNC_SIZE};
+use crate::format::{Format, Formatter, Projection};
 use crate::model::Record;
 use clap::Parser;
 use std::fs::File;
@@ -11,38 +13,197 @@ pub struct FileOutput {
     /// Format of the output records.
     #[clap(short, long)]
     format: Option<Format>,
+    /// Compression codec of the blocks when writing an avro file.
+    #[cfg(feature = "formatter-avro")]
+    #[clap(long, default_value = "null")]
+    codec: Codec,
+    /// Number of records per block when writing an avro file.
+    #[cfg(feature = "formatter-avro")]
+    #[clap(long, default_value = "64")]
+    block_size: usize,
     /// Path to write the file.
     #[clap()]
     output: PathBuf,
 }
 
 impl FileOutput {
-    pub fn exporter(&self) -> Box<dyn Exporter> {
+    pub fn exporter(&self, projection: Projection) -> Box<dyn Exporter> {
         let file = File::options()
             .create(true)
-            .write(true)
             .append(true)
+            .read(true)
             .open(&self.output)
             .expect("couldn't open output file");
-        let formatter = self.format.clone().unwrap_or_default().formatter();
-        Box::new(FileExporter { file, formatter })
+        let format = self.format.clone().unwrap_or_default();
+        #[cfg(feature = "formatter-avro")]
+        if let Format::Avro = format {
+            return Box::new(
+                AvroFileExporter::new(file, &projection, self.codec, self.block_size)
+                    .expect("couldn't prepare avro file"),
+            );
+        }
+        let formatter = format.formatter(projection);
+        Box::new(FileExporter::new(file, formatter))
     }
 }
 
 pub struct FileExporter {
     file: File,
     formatter: Box<dyn Formatter>,
+    buffer: Vec<u8>,
+}
+
+impl FileExporter {
+    pub fn new(file: File, formatter: Box<dyn Formatter>) -> Self {
+        Self {
+            file,
+            formatter,
+            buffer: Vec::new(),
+        }
+    }
 }
 
 impl Exporter for FileExporter {
     fn handle(&mut self, record: Record) -> Result<(), String> {
-        let mut line = self.formatter.format(record)?;
-        line.push_str(super::LINE_ENDING);
+        self.buffer.clear();
+        self.formatter.format(&record, &mut self.buffer)?;
         self.file
-            .write_all(line.as_bytes())
+            .write_all(&self.buffer)
+            .map_err(|err| err.to_string())?;
+        Ok(())
+    }
+}
+
+/// Writes the records in an avro object container file.
+#[cfg(feature = "formatter-avro")]
+pub struct AvroFileExporter {
+    file: File,
+    schema: Schema,
+    codec: Codec,
+    sync: [u8; SYNC_SIZE],
+    block: Vec<u8>,
+    block_count: usize,
+    block_size: usize,
+}
+
+#[cfg(feature = "formatter-avro")]
+impl AvroFileExporter {
+    pub fn new(
+        mut file: File,
+        projection: &Projection,
+        codec: Codec,
+        block_size: usize,
+    ) -> Result<Self, String> {
+        let schema = Schema::new(projection)?;
+        let is_empty = file.metadata().map_err(|err| err.to_string())?.len() == 0;
+        let sync = if is_empty {
+            let header = Header::new(&schema, codec);
+            let mut output = Vec::new();
+            header
+                .encode(&mut output)
+                .and_then(|_| file.write_all(&output))
+                .map_err(|err| err.to_string())?;
+            header.sync
+        } else {
+            let header = Header::read(&mut file)?;
+            if header.schema != schema.to_json() {
+                return Err("the existing file has a different schema".into());
+            }
+            if header.codec != codec.name() {
+                return Err(format!(
+                    "the existing file uses the {:?} codec",
+                    header.codec
+                ));
+            }
+            header.sync
+        };
+        Ok(Self {
+            file,
+            schema,
+            codec,
+            sync,
+            block: Vec::new(),
+            block_count: 0,
+            block_size: block_size.max(1),
+        })
+    }
+
+    fn flush_block(&mut self) -> Result<(), String> {
+        if self.block_count == 0 {
+            return Ok(());
+        }
+        let data = self.codec.compress(std::mem::take(&mut self.block))?;
+        let mut output = Vec::with_capacity(data.len() + SYNC_SIZE + 20);
+        encode_long(self.block_count as i64, &mut output)
+            .and_then(|_| encode_bytes(&data, &mut output))
+            .map_err(|err| err.to_string())?;
+        output.extend_from_slice(&self.sync);
+        self.block_count = 0;
+        self.file.write_all(&output).map_err(|err| err.to_string())
+    }
+}
+
+#[cfg(feature = "formatter-avro")]
+impl Exporter for AvroFileExporter {
+    fn handle(&mut self, record: Record) -> Result<(), String> {
+        self.schema
+            .encode(&record, &mut self.block)
             .map_err(|err| err.to_string())?;
+        self.block_count += 1;
+        if self.block_count >= self.block_size {
+            self.flush_block()?;
+        }
         Ok(())
     }
+
+    fn close(&mut self) -> Result<(), String> {
+        self.flush_block()?;
+        self.file.flush().map_err(|err| err.to_string())
+    }
+}
+
+#[cfg(all(test, feature = "formatter-avro"))]
+mod avro_tests {
+    use super::AvroFileExporter;
+    use crate::exporter::Exporter;
+    use crate::format::avro::{Codec, Header, SYNC_SIZE};
+    use crate::format::Projection;
+    use crate::model::Record;
+    use std::fs::File;
+
+    fn open(path: &std::path::Path) -> File {
+        File::options()
+            .create(true)
+            .append(true)
+            .read(true)
+            .open(path)
+            .unwrap()
+    }
+
+    #[test]
+    fn should_append_blocks_to_existing_file() {
+        let tmp = std::env::temp_dir().join("output.avro");
+        let _ = std::fs::remove_file(&tmp);
+        let projection = Projection::default();
+        let mut exporter = AvroFileExporter::new(open(&tmp), &projection, Codec::Null, 2).unwrap();
+        for _ in 0..3 {
+            exporter.handle(Record::random()).unwrap();
+        }
+        exporter.close().unwrap();
+        let mut exporter = AvroFileExporter::new(open(&tmp), &projection, Codec::Null, 2).unwrap();
+        exporter.handle(Record::random()).unwrap();
+        exporter.close().unwrap();
+
+        let data = std::fs::read(&tmp).unwrap();
+        let mut reader = data.as_slice();
+        let header = Header::read(&mut reader).unwrap();
+        let syncs = reader
+            .windows(SYNC_SIZE)
+            .filter(|window| *window == header.sync)
+            .count();
+        assert_eq!(syncs, 3);
+        assert!(AvroFileExporter::new(open(&tmp), &projection, Codec::Snappy, 2).is_err());
+    }
 }
 
 #[cfg(all(test, feature = "formatter-json"))]
@@ -56,9 +217,9 @@ mod tests {
     async fn unix_socket_should_receive() {
         let tmp = std::env::temp_dir().join("output.jsonp");
         let file = File::create(&tmp).unwrap();
-        let formatter = Box::new(crate::format::json::JsonFormatter);
+        let formatter = Box::new(crate::format::json::JsonFormatter::default());
         let source = Record::random();
-        let mut exporter = FileExporter { file, formatter };
+        let mut exporter = FileExporter::new(file, formatter);
         exporter.handle(source.clone()).unwrap();
         let data = std::fs::read_to_string(tmp).unwrap();
         assert_eq!(serde_json::to_string(&source).unwrap(), data.trim());
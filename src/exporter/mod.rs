@@ -1,14 +1,35 @@
 mod file;
 mod socket;
 mod stdout;
+mod table;
 
-use crate::model::Record;
+use crate::format::Projection;
+use crate::model::{Message, Record};
 use clap::Parser;
 
-const LINE_ENDING: &str = "\n";
-
 pub trait Exporter {
     fn handle(&mut self, record: Record) -> Result<(), String>;
+
+    fn started(&mut self, _container_name: &str) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn stopped(&mut self, _container_name: &str) -> Result<(), String> {
+        Ok(())
+    }
+
+    /// Called once all the records have been handled, before exiting.
+    fn close(&mut self) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn handle_message(&mut self, message: Message) -> Result<(), String> {
+        match message {
+            Message::Started(name) => self.started(&name),
+            Message::Record(record) => self.handle(*record),
+            Message::Stopped(name) => self.stopped(&name),
+        }
+    }
 }
 
 #[derive(Parser)]
@@ -29,13 +50,13 @@ pub enum Output {
 }
 
 impl Output {
-    pub fn exporter(&self) -> Box<dyn Exporter> {
+    pub fn exporter(&self, projection: Projection) -> Box<dyn Exporter> {
         match self {
-            Self::File(file) => file.exporter(),
+            Self::File(file) => file.exporter(projection),
             #[cfg(not(windows))]
-            Self::UnixSocket(socket) => socket.exporter(),
-            Self::TcpSocket(socket) => socket.exporter(),
-            Self::Stdout(socket) => socket.exporter(),
+            Self::UnixSocket(socket) => socket.exporter(projection),
+            Self::TcpSocket(socket) => socket.exporter(projection),
+            Self::Stdout(socket) => socket.exporter(projection),
         }
     }
 }
@@ -1,5 +1,7 @@
 use crate::exporter::Exporter;
-use crate::format::{Format, Formatter};
+#[cfg(feature = "formatter-avro")]
+use crate::format::avro::AvroFormatter;
+use crate::format::{Format, Formatter, Projection};
 use crate::model::Record;
 use clap::Parser;
 use std::io::Write;
@@ -7,12 +9,30 @@ use std::net::TcpStream;
 use std::os::unix::net::UnixStream;
 use std::path::PathBuf;
 
+fn socket_formatter(
+    format: Format,
+    projection: Projection,
+    #[cfg(feature = "formatter-avro")] schema_id: Option<u32>,
+) -> Box<dyn Formatter> {
+    #[cfg(feature = "formatter-avro")]
+    if let (Format::Avro, Some(schema_id)) = (&format, schema_id) {
+        return Box::new(
+            AvroFormatter::confluent(&projection, schema_id).expect("invalid avro schema"),
+        );
+    }
+    format.formatter(projection)
+}
+
 #[cfg(not(windows))]
 #[derive(Parser)]
 pub struct UnixSocketOutput {
     /// Format of the output records.
     #[clap(short, long)]
     format: Option<Format>,
+    /// Schema registry id of the avro schema, to use the confluent wire format.
+    #[cfg(feature = "formatter-avro")]
+    #[clap(long)]
+    schema_id: Option<u32>,
     /// Path to the unix socket.
     #[clap()]
     output: PathBuf,
@@ -20,10 +40,15 @@ pub struct UnixSocketOutput {
 
 #[cfg(not(windows))]
 impl UnixSocketOutput {
-    pub fn exporter(&self) -> Box<dyn Exporter> {
+    pub fn exporter(&self, projection: Projection) -> Box<dyn Exporter> {
         let stream = Box::new(UnixStream::connect(&self.output).expect("couldn't open socket"));
-        let formatter = self.format.clone().unwrap_or_default().formatter();
-        Box::new(SocketExporter { stream, formatter })
+        let formatter = socket_formatter(
+            self.format.clone().unwrap_or_default(),
+            projection,
+            #[cfg(feature = "formatter-avro")]
+            self.schema_id,
+        );
+        Box::new(SocketExporter::new(stream, formatter))
     }
 }
 
@@ -32,30 +57,50 @@ pub struct TcpSocketOutput {
     /// Format of the output records.
     #[clap(short, long)]
     format: Option<Format>,
+    /// Schema registry id of the avro schema, to use the confluent wire format.
+    #[cfg(feature = "formatter-avro")]
+    #[clap(long)]
+    schema_id: Option<u32>,
     /// Server address.
     #[clap()]
     address: String,
 }
 
 impl TcpSocketOutput {
-    pub fn exporter(&self) -> Box<dyn Exporter> {
+    pub fn exporter(&self, projection: Projection) -> Box<dyn Exporter> {
         let stream = Box::new(TcpStream::connect(&self.address).expect("couldn't open socket"));
-        let formatter = self.format.clone().unwrap_or_default().formatter();
-        Box::new(SocketExporter { stream, formatter })
+        let formatter = socket_formatter(
+            self.format.clone().unwrap_or_default(),
+            projection,
+            #[cfg(feature = "formatter-avro")]
+            self.schema_id,
+        );
+        Box::new(SocketExporter::new(stream, formatter))
     }
 }
 
 pub struct SocketExporter {
     stream: Box<dyn Write>,
     formatter: Box<dyn Formatter>,
+    buffer: Vec<u8>,
+}
+
+impl SocketExporter {
+    pub fn new(stream: Box<dyn Write>, formatter: Box<dyn Formatter>) -> Self {
+        Self {
+            stream,
+            formatter,
+            buffer: Vec::new(),
+        }
+    }
 }
 
 impl Exporter for SocketExporter {
     fn handle(&mut self, record: Record) -> Result<(), String> {
-        let mut line = self.formatter.format(record)?;
-        line.push_str(super::LINE_ENDING);
+        self.buffer.clear();
+        self.formatter.format(&record, &mut self.buffer)?;
         self.stream
-            .write_all(line.as_bytes())
+            .write_all(&self.buffer)
             .map_err(|err| err.to_string())?;
         Ok(())
     }
@@ -66,24 +111,23 @@ mod tests {
     use super::SocketExporter;
     use crate::exporter::Exporter;
     use crate::model::Record;
-    use std::io::Read;
+    use std::io::{BufRead, BufReader};
     use std::os::unix::net::UnixStream;
     use tokio::sync::mpsc;
 
     #[tokio::test]
     async fn unix_socket_should_receive() {
         let (tx, mut rx) = mpsc::channel::<String>(10);
-        let (ts, mut rs) = UnixStream::pair().unwrap();
+        let (ts, rs) = UnixStream::pair().unwrap();
         let handler = tokio::spawn(async move {
-            let mut buffer: [u8; 170] = [0; 170];
-            rs.read(&mut buffer).unwrap();
-            let buffer = std::str::from_utf8(&buffer).unwrap().to_string();
+            let mut buffer = String::new();
+            BufReader::new(rs).read_line(&mut buffer).unwrap();
             tx.send(buffer).await.unwrap();
         });
-        let mut exporter = SocketExporter {
-            stream: Box::new(ts),
-            formatter: Box::new(crate::format::json::JsonFormatter),
-        };
+        let mut exporter = SocketExporter::new(
+            Box::new(ts),
+            Box::new(crate::format::json::JsonFormatter::default()),
+        );
         exporter.handle(Record::random()).unwrap();
         handler.await.unwrap();
         let mut result = Vec::with_capacity(1);
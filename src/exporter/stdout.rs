@@ -1,5 +1,6 @@
+use crate::exporter::table::TableExporter;
 use crate::exporter::Exporter;
-use crate::format::{Format, Formatter};
+use crate::format::{Format, Formatter, Projection};
 use crate::model::Record;
 use clap::Parser;
 use std::io::{self, Write};
@@ -9,27 +10,48 @@ pub struct StdOutOutput {
     /// Format of the output records.
     #[clap(short, long)]
     format: Option<Format>,
+    /// Render a live table, with one row per container, instead of the records.
+    #[clap(short, long, conflicts_with = "format")]
+    table: bool,
 }
 
 impl StdOutOutput {
-    pub fn exporter(&self) -> Box<dyn Exporter> {
+    pub fn exporter(&self, projection: Projection) -> Box<dyn Exporter> {
+        if self.table {
+            return Box::new(TableExporter::new(io::stdout()));
+        }
         let inner = io::stdout();
-        let formatter = self.format.clone().unwrap_or_default().formatter();
-        Box::new(StdOutExporter { inner, formatter })
+        let formatter = self
+            .format
+            .clone()
+            .unwrap_or_default()
+            .formatter(projection);
+        Box::new(StdOutExporter::new(inner, formatter))
     }
 }
 
 pub struct StdOutExporter {
     inner: io::Stdout,
     formatter: Box<dyn Formatter>,
+    buffer: Vec<u8>,
+}
+
+impl StdOutExporter {
+    pub fn new(inner: io::Stdout, formatter: Box<dyn Formatter>) -> Self {
+        Self {
+            inner,
+            formatter,
+            buffer: Vec::new(),
+        }
+    }
 }
 
 impl Exporter for StdOutExporter {
     fn handle(&mut self, record: Record) -> Result<(), String> {
-        let mut line = self.formatter.format(record)?;
-        line.push_str(super::LINE_ENDING);
+        self.buffer.clear();
+        self.formatter.format(&record, &mut self.buffer)?;
         self.inner
-            .write_all(line.as_bytes())
+            .write_all(&self.buffer)
             .map_err(|err| err.to_string())?;
         Ok(())
     }
@@ -44,9 +66,9 @@ mod tests {
     #[tokio::test]
     async fn unix_socket_should_receive() {
         let inner = std::io::stdout();
-        let formatter = Box::new(crate::format::json::JsonFormatter);
+        let formatter = Box::new(crate::format::json::JsonFormatter::default());
         let source = Record::random();
-        let mut exporter = StdOutExporter { inner, formatter };
+        let mut exporter = StdOutExporter::new(inner, formatter);
         assert!(exporter.handle(source.clone()).is_ok());
     }
 }
@@ -0,0 +1,261 @@
+use crate::exporter::Exporter;
+#[cfg(feature = "enrichment-powercap")]
+use crate::format::EnergyUnit;
+use crate::model::{BlockUsage, NetworkUsage, Record, RecordType};
+#[cfg(feature = "enrichment-powercap")]
+use chrono::{DateTime, Utc};
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+use std::time::{Duration, Instant};
+
+const CLEAR_SCREEN: &str = "\x1b[2J\x1b[H";
+const REFRESH_INTERVAL: Duration = Duration::from_millis(500);
+const MEBIBYTE: f64 = 1024.0 * 1024.0;
+
+#[derive(Default)]
+struct Row {
+    container_id: String,
+    cpu_percent: Option<f64>,
+    memory_usage: Option<u64>,
+    memory_limit: Option<u64>,
+    memory_percent: Option<f64>,
+    pid_count: Option<u64>,
+    network: Option<NetworkUsage>,
+    block_io: Option<BlockUsage>,
+    #[cfg(feature = "enrichment-powercap")]
+    last_ts: Option<DateTime<Utc>>,
+    #[cfg(feature = "enrichment-powercap")]
+    power: Option<f64>,
+    #[cfg(feature = "enrichment-powercap")]
+    energy: Option<f64>,
+}
+
+impl Row {
+    fn update(&mut self, record: Record) {
+        #[cfg(feature = "enrichment-powercap")]
+        if let Some(energy) = record
+            .attributed_energy()
+            .map(|value| EnergyUnit::Joules.convert(value))
+        {
+            self.power = self
+                .last_ts
+                .map(|last| (record.ts - last).num_milliseconds() as f64 / 1_000.0)
+                .filter(|elapsed| *elapsed > 0.0)
+                .map(|elapsed| energy / elapsed);
+            self.energy = Some(self.energy.unwrap_or_default() + energy);
+        }
+        #[cfg(feature = "enrichment-powercap")]
+        {
+            self.last_ts = Some(record.ts);
+        }
+        self.container_id = record.container_id;
+        self.cpu_percent = record.cpu_docker_percent;
+        // like docker stats, the usage excludes the inactive page cache
+        self.memory_usage = record.memory_working_set.or(record.memory_usage);
+        self.memory_percent = record.memory_percent;
+        self.memory_limit = record.memory_limit;
+        self.pid_count = record.pid_count;
+        self.network = record.network;
+        self.block_io = record.block_io;
+    }
+
+    fn columns(&self, name: &str) -> Vec<String> {
+        vec![
+            self.container_id.chars().take(12).collect(),
+            name.to_string(),
+            display(self.cpu_percent, |value| format!("{:.2}%", value)),
+            format!(
+                "{} / {}",
+                display(self.memory_usage, mebibytes),
+                display(self.memory_limit, mebibytes)
+            ),
+            display(self.memory_percent, |value| format!("{:.2}%", value)),
+            display(self.network, |value| {
+                format!(
+                    "{} / {}",
+                    mebibytes(value.rx_bytes),
+                    mebibytes(value.tx_bytes)
+                )
+            }),
+            display(self.block_io, |value| {
+                format!(
+                    "{} / {}",
+                    mebibytes(value.read_bytes),
+                    mebibytes(value.write_bytes)
+                )
+            }),
+            display(self.pid_count, |value| value.to_string()),
+            #[cfg(feature = "enrichment-powercap")]
+            display(self.power, |value| format!("{:.2}W", value)),
+            #[cfg(feature = "enrichment-powercap")]
+            display(self.energy, |value| format!("{:.2}J", value)),
+        ]
+    }
+}
+
+fn display<T, F: Fn(T) -> String>(value: Option<T>, format: F) -> String {
+    value.map(format).unwrap_or_else(|| "--".into())
+}
+
+fn mebibytes(value: u64) -> String {
+    format!("{:.1}MiB", value as f64 / MEBIBYTE)
+}
+
+const HEADERS: &[&str] = &[
+    "CONTAINER ID",
+    "NAME",
+    "CPU %",
+    "MEM USAGE / LIMIT",
+    "MEM %",
+    "NET I/O",
+    "BLOCK I/O",
+    "PIDS",
+    #[cfg(feature = "enrichment-powercap")]
+    "POWER",
+    #[cfg(feature = "enrichment-powercap")]
+    "ENERGY",
+];
+
+/// Exporter rendering a refreshing table, with one row per watched container.
+pub struct TableExporter<W> {
+    inner: W,
+    rows: BTreeMap<String, Row>,
+    last_render: Option<Instant>,
+}
+
+impl<W: Write> TableExporter<W> {
+    pub fn new(inner: W) -> Self {
+        Self {
+            inner,
+            rows: BTreeMap::new(),
+            last_render: None,
+        }
+    }
+
+    fn render(&mut self) -> io::Result<()> {
+        let lines: Vec<Vec<String>> =
+            std::iter::once(HEADERS.iter().map(|h| h.to_string()).collect())
+                .chain(self.rows.iter().map(|(name, row)| row.columns(name)))
+                .collect();
+        let widths: Vec<usize> = (0..HEADERS.len())
+            .map(|index| {
+                lines
+                    .iter()
+                    .map(|line| line[index].len())
+                    .max()
+                    .unwrap_or_default()
+            })
+            .collect();
+        let mut output = String::from(CLEAR_SCREEN);
+        for line in lines {
+            let cells: Vec<String> = line
+                .iter()
+                .zip(widths.iter())
+                .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+                .collect();
+            output.push_str(cells.join("   ").trim_end());
+            output.push_str(crate::format::LINE_ENDING);
+        }
+        self.inner.write_all(output.as_bytes())?;
+        self.inner.flush()?;
+        self.last_render = Some(Instant::now());
+        Ok(())
+    }
+
+    fn refresh(&mut self, force: bool) -> Result<(), String> {
+        let outdated = self
+            .last_render
+            .map(|last| last.elapsed() >= REFRESH_INTERVAL)
+            .unwrap_or(true);
+        if force || outdated {
+            self.render().map_err(|err| err.to_string())?;
+        }
+        Ok(())
+    }
+}
+
+impl<W: Write> Exporter for TableExporter<W> {
+    fn handle(&mut self, record: Record) -> Result<(), String> {
+        if record.record_type != RecordType::Sample {
+            return Ok(());
+        }
+        self.rows
+            .entry(record.container_name.clone())
+            .or_default()
+            .update(record);
+        self.refresh(false)
+    }
+
+    fn started(&mut self, container_name: &str) -> Result<(), String> {
+        self.rows.entry(container_name.to_string()).or_default();
+        self.refresh(true)
+    }
+
+    fn stopped(&mut self, container_name: &str) -> Result<(), String> {
+        self.rows.remove(container_name);
+        self.refresh(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TableExporter;
+    use crate::exporter::Exporter;
+    use crate::model::{ContainerEvent, Record};
+
+    fn last_frame(exporter: &TableExporter<Vec<u8>>) -> String {
+        let output = String::from_utf8(exporter.inner.clone()).unwrap();
+        output
+            .rsplit(super::CLEAR_SCREEN)
+            .next()
+            .unwrap()
+            .to_string()
+    }
+
+    #[test]
+    fn rows_should_follow_watchers() {
+        let mut exporter = TableExporter::new(Vec::new());
+        exporter.started("world").unwrap();
+        assert!(last_frame(&exporter).contains("world"));
+        exporter.handle(Record::random()).unwrap();
+        exporter.stopped("world").unwrap();
+        let frame = last_frame(&exporter);
+        assert!(frame.starts_with("CONTAINER ID"));
+        assert!(!frame.contains("world"));
+    }
+
+    #[test]
+    fn rows_should_ignore_events() {
+        let mut exporter = TableExporter::new(Vec::new());
+        let sample = Record::random();
+        let event = Record::event(
+            "other".into(),
+            "other".into(),
+            sample.ts,
+            ContainerEvent {
+                action: "oom".into(),
+                exit_code: None,
+                signal: None,
+            },
+        );
+        exporter.handle(sample).unwrap();
+        exporter.handle(event).unwrap();
+        assert_eq!(exporter.rows.len(), 1);
+    }
+
+    #[test]
+    fn rows_should_use_human_readable_units() {
+        let mut exporter = TableExporter::new(Vec::new());
+        let mut record = Record::random();
+        record.memory_usage = Some(640 * 1024 * 1024);
+        record.memory_working_set = Some(512 * 1024 * 1024);
+        record.memory_limit = Some(1024 * 1024 * 1024);
+        record.memory_percent = Some(50.0);
+        exporter.handle(record).unwrap();
+        let frame = last_frame(&exporter);
+        assert!(frame.contains("178.00%"));
+        assert!(frame.contains("512.0MiB / 1024.0MiB"));
+        assert!(frame.contains("50.00%"));
+        assert!(frame.contains("0.0MiB / 0.0MiB"));
+    }
+}
@@ -0,0 +1,619 @@
+//! Encoding of the records using [Apache Avro](https://avro.apache.org/docs/current/spec.html).
+
+use super::{Formatter, Projection, Units};
+use crate::model::{Field, Kind, Record, Value};
+use std::collections::hash_map::RandomState;
+use std::convert::TryFrom;
+use std::fmt::Write as _;
+use std::hash::{BuildHasher, Hasher};
+use std::io::{self, Read, Write};
+use std::str::FromStr;
+use std::time::SystemTime;
+
+const NAMESPACE: &str = "docker_activity";
+const NAME: &str = "Record";
+const OBJECT_CONTAINER_MAGIC: [u8; 4] = [b'O', b'b', b'j', 0x01];
+pub const SYNC_SIZE: usize = 16;
+const SINGLE_OBJECT_MARKER: [u8; 2] = [0xC3, 0x01];
+const CONFLUENT_MARKER: u8 = 0x00;
+const FINGERPRINT_EMPTY: u64 = 0xc15d_213a_a4d7_a795;
+
+/// Cargo features enabled when building, to keep track of what produced the records.
+pub const FEATURES: &[&str] = &[
+    #[cfg(feature = "enrichment-carbon")]
+    "enrichment-carbon",
+    #[cfg(feature = "enrichment-cost")]
+    "enrichment-cost",
+    #[cfg(feature = "enrichment-powercap")]
+    "enrichment-powercap",
+    #[cfg(feature = "enrichment-sensors")]
+    "enrichment-sensors",
+    #[cfg(feature = "formatter-avro")]
+    "formatter-avro",
+    #[cfg(feature = "formatter-csv")]
+    "formatter-csv",
+    #[cfg(feature = "formatter-json")]
+    "formatter-json",
+];
+
+/// Compression codec of the blocks of an object container file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Codec {
+    Null,
+    Deflate,
+    Snappy,
+}
+
+impl FromStr for Codec {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        match input {
+            "null" => Ok(Self::Null),
+            "deflate" => Ok(Self::Deflate),
+            "snappy" => Ok(Self::Snappy),
+            other => Err(format!("unknown codec {:?}", other)),
+        }
+    }
+}
+
+impl Codec {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Null => "null",
+            Self::Deflate => "deflate",
+            Self::Snappy => "snappy",
+        }
+    }
+
+    pub fn compress(&self, data: Vec<u8>) -> Result<Vec<u8>, String> {
+        match self {
+            Self::Null => Ok(data),
+            Self::Deflate => {
+                let mut encoder = flate2::write::DeflateEncoder::new(
+                    Vec::with_capacity(data.len()),
+                    flate2::Compression::default(),
+                );
+                encoder.write_all(&data).map_err(|err| err.to_string())?;
+                encoder.finish().map_err(|err| err.to_string())
+            }
+            Self::Snappy => {
+                let mut result = snap::raw::Encoder::new()
+                    .compress_vec(&data)
+                    .map_err(|err| err.to_string())?;
+                result.extend_from_slice(&crc32fast::hash(&data).to_be_bytes());
+                Ok(result)
+            }
+        }
+    }
+}
+
+fn is_valid_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    matches!(chars.next(), Some(first) if first.is_ascii_alphabetic() || first == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Writes the avro type of the values, the canonical form using full names
+/// for the nested records.
+fn write_kind(output: &mut String, kind: Kind, canonical: bool) {
+    match kind {
+        Kind::Boolean => output.push_str("\"boolean\""),
+        Kind::Integer => output.push_str("\"long\""),
+        Kind::Float => output.push_str("\"double\""),
+        Kind::Text => output.push_str("\"string\""),
+        Kind::Nullable(inner) => {
+            output.push_str("[\"null\",");
+            write_kind(output, *inner, canonical);
+            output.push(']');
+        }
+        Kind::List(inner) => {
+            output.push_str("{\"type\":\"array\",\"items\":");
+            write_kind(output, *inner, canonical);
+            output.push('}');
+        }
+        Kind::Map(inner) => {
+            output.push_str("{\"type\":\"map\",\"values\":");
+            write_kind(output, *inner, canonical);
+            output.push('}');
+        }
+        Kind::Record(name, fields) => {
+            if canonical {
+                let _ = write!(
+                    output,
+                    "{{\"name\":\"{}.{}\",\"type\":\"record\"",
+                    NAMESPACE, name
+                );
+            } else {
+                let _ = write!(output, "{{\"type\":\"record\",\"name\":\"{}\"", name);
+            }
+            output.push_str(",\"fields\":[");
+            for (index, (name, kind)) in fields.iter().enumerate() {
+                if index > 0 {
+                    output.push(',');
+                }
+                let _ = write!(output, "{{\"name\":\"{}\",\"type\":", name);
+                write_kind(output, *kind, canonical);
+                output.push('}');
+            }
+            output.push_str("]}");
+        }
+    }
+}
+
+/// Writes the binary encoding of the value, using the default of the type for
+/// the missing values that are not nullable.
+fn encode_value<W: Write + ?Sized>(kind: Kind, value: &Value, output: &mut W) -> io::Result<()> {
+    match (kind, value) {
+        (Kind::Nullable(_), Value::Null) => encode_long(0, output),
+        (Kind::Nullable(inner), value) => {
+            encode_long(1, output)?;
+            encode_value(*inner, value, output)
+        }
+        (Kind::Boolean, Value::Boolean(value)) => output.write_all(&[*value as u8]),
+        (Kind::Boolean, _) => output.write_all(&[0]),
+        (Kind::Integer, Value::Integer(value)) => encode_long(*value, output),
+        (Kind::Integer, Value::Unsigned(value)) => encode_long(*value as i64, output),
+        (Kind::Integer, _) => encode_long(0, output),
+        (Kind::Float, Value::Float(value)) => output.write_all(&value.to_le_bytes()),
+        (Kind::Float, _) => output.write_all(&0f64.to_le_bytes()),
+        (Kind::Text, Value::Text(value)) => encode_bytes(value.as_bytes(), output),
+        (Kind::Text, _) => encode_bytes(&[], output),
+        (Kind::Map(inner), Value::Map(entries)) => {
+            if !entries.is_empty() {
+                encode_long(entries.len() as i64, output)?;
+                for (key, value) in entries {
+                    encode_bytes(key.as_bytes(), output)?;
+                    encode_value(*inner, value, output)?;
+                }
+            }
+            encode_long(0, output)
+        }
+        (Kind::Map(_), _) => encode_long(0, output),
+        (Kind::List(inner), Value::List(items)) => {
+            if !items.is_empty() {
+                encode_long(items.len() as i64, output)?;
+                for item in items {
+                    encode_value(*inner, item, output)?;
+                }
+            }
+            encode_long(0, output)
+        }
+        (Kind::List(_), _) => encode_long(0, output),
+        (Kind::Record(_, fields), value) => {
+            for (name, kind) in fields.iter() {
+                let field = match value {
+                    Value::Map(entries) => entries.iter().find(|(key, _)| key == name),
+                    _ => None,
+                };
+                match field {
+                    Some((_, value)) => encode_value(*kind, value, output)?,
+                    None => encode_value(*kind, &Value::Null, output)?,
+                }
+            }
+            Ok(())
+        }
+    }
+}
+
+fn field_doc(field: Field) -> String {
+    match field.feature() {
+        Some(feature) => format!("{} (from the {} feature)", field.name(), feature),
+        None => field.name().to_string(),
+    }
+}
+
+/// Avro schema of the records, built from the projection of the fields.
+pub struct Schema {
+    fields: Vec<(String, Field, Kind)>,
+    units: Units,
+}
+
+impl Schema {
+    pub fn new(projection: &Projection) -> Result<Self, String> {
+        let fields: Vec<(String, Field, Kind)> = projection
+            .fields()
+            .map(|(field, name, kind)| (name.to_string(), field, kind))
+            .collect();
+        if let Some((name, _, _)) = fields.iter().find(|(name, _, _)| !is_valid_name(name)) {
+            return Err(format!("{:?} is not a valid avro field name", name));
+        }
+        Ok(Self {
+            fields,
+            units: projection.units().clone(),
+        })
+    }
+
+    fn write_type(output: &mut String, field: Field, kind: Kind, canonical: bool) {
+        if field.nullable() {
+            output.push_str("[\"null\",");
+            write_kind(output, kind, canonical);
+            output.push(']');
+        } else {
+            write_kind(output, kind, canonical);
+        }
+    }
+
+    /// JSON representation of the schema, with the documentation of the fields.
+    pub fn to_json(&self) -> String {
+        let mut output = format!(
+            "{{\"type\":\"record\",\"name\":\"{}\",\"namespace\":\"{}\",\"doc\":\"features: {}\",\"fields\":[",
+            NAME,
+            NAMESPACE,
+            FEATURES.join(",")
+        );
+        for (index, (name, field, kind)) in self.fields.iter().enumerate() {
+            if index > 0 {
+                output.push(',');
+            }
+            let _ = write!(output, "{{\"name\":\"{}\",\"type\":", name);
+            Self::write_type(&mut output, *field, *kind, false);
+            if field.nullable() {
+                output.push_str(",\"default\":null");
+            }
+            let _ = write!(output, ",\"doc\":\"{}\"}}", field_doc(*field));
+        }
+        output.push_str("]}");
+        output
+    }
+
+    /// Parsing canonical form of the schema, used to compute its fingerprint.
+    pub fn to_canonical(&self) -> String {
+        let mut output = format!(
+            "{{\"name\":\"{}.{}\",\"type\":\"record\",\"fields\":[",
+            NAMESPACE, NAME
+        );
+        for (index, (name, field, kind)) in self.fields.iter().enumerate() {
+            if index > 0 {
+                output.push(',');
+            }
+            let _ = write!(output, "{{\"name\":\"{}\",\"type\":", name);
+            Self::write_type(&mut output, *field, *kind, true);
+            output.push('}');
+        }
+        output.push_str("]}");
+        output
+    }
+
+    /// CRC-64-AVRO fingerprint of the canonical form of the schema.
+    pub fn fingerprint(&self) -> u64 {
+        let mut table = [0u64; 256];
+        for (index, item) in table.iter_mut().enumerate() {
+            let mut fp = index as u64;
+            for _ in 0..8 {
+                fp = (fp >> 1) ^ (FINGERPRINT_EMPTY & (0u64.wrapping_sub(fp & 1)));
+            }
+            *item = fp;
+        }
+        self.to_canonical()
+            .bytes()
+            .fold(FINGERPRINT_EMPTY, |fp, byte| {
+                (fp >> 8) ^ table[((fp ^ byte as u64) & 0xff) as usize]
+            })
+    }
+
+    /// Writes the binary encoding of the record to the output.
+    pub fn encode<W: Write + ?Sized>(&self, record: &Record, output: &mut W) -> io::Result<()> {
+        for (_, field, kind) in self.fields.iter() {
+            let value = self.units.value(record, *field);
+            if field.nullable() {
+                if value == Value::Null {
+                    encode_long(0, output)?;
+                    continue;
+                }
+                encode_long(1, output)?;
+            }
+            encode_value(*kind, &value, output)?;
+        }
+        Ok(())
+    }
+}
+
+pub fn encode_long<W: Write + ?Sized>(value: i64, output: &mut W) -> io::Result<()> {
+    let mut buffer = [0u8; 10];
+    let mut length = 0;
+    let mut value = ((value << 1) ^ (value >> 63)) as u64;
+    while value > 0x7f {
+        buffer[length] = (value as u8 & 0x7f) | 0x80;
+        length += 1;
+        value >>= 7;
+    }
+    buffer[length] = value as u8;
+    output.write_all(&buffer[..=length])
+}
+
+pub fn encode_bytes<W: Write + ?Sized>(value: &[u8], output: &mut W) -> io::Result<()> {
+    encode_long(value.len() as i64, output)?;
+    output.write_all(value)
+}
+
+fn decode_long<R: Read>(input: &mut R) -> Result<i64, String> {
+    let mut value = 0u64;
+    let mut shift = 0;
+    loop {
+        let mut byte = [0u8; 1];
+        input.read_exact(&mut byte).map_err(|err| err.to_string())?;
+        value |= ((byte[0] & 0x7f) as u64) << shift;
+        if byte[0] & 0x80 == 0 {
+            break;
+        }
+        shift += 7;
+        if shift > 63 {
+            return Err("invalid variable length integer".into());
+        }
+    }
+    Ok((value >> 1) as i64 ^ -((value & 1) as i64))
+}
+
+fn decode_bytes<R: Read>(input: &mut R) -> Result<Vec<u8>, String> {
+    let length = decode_long(input)?;
+    let mut result = vec![0u8; usize::try_from(length).map_err(|err| err.to_string())?];
+    input
+        .read_exact(&mut result)
+        .map_err(|err| err.to_string())?;
+    Ok(result)
+}
+
+/// Header of an object container file.
+pub struct Header {
+    pub schema: String,
+    pub codec: String,
+    pub sync: [u8; SYNC_SIZE],
+}
+
+impl Header {
+    pub fn new(schema: &Schema, codec: Codec) -> Self {
+        let mut sync = [0u8; SYNC_SIZE];
+        let seed = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_nanos())
+            .unwrap_or_default();
+        for chunk in sync.chunks_mut(8) {
+            let mut hasher = RandomState::new().build_hasher();
+            hasher.write_u128(seed);
+            chunk.copy_from_slice(&hasher.finish().to_le_bytes());
+        }
+        Self {
+            schema: schema.to_json(),
+            codec: codec.name().to_string(),
+            sync,
+        }
+    }
+
+    pub fn read<R: Read>(input: &mut R) -> Result<Self, String> {
+        let mut magic = [0u8; 4];
+        input
+            .read_exact(&mut magic)
+            .map_err(|err| err.to_string())?;
+        if magic != OBJECT_CONTAINER_MAGIC {
+            return Err("not an avro object container file".into());
+        }
+        let mut schema = None;
+        let mut codec = None;
+        loop {
+            let count = decode_long(input)?;
+            if count == 0 {
+                break;
+            }
+            if count < 0 {
+                // the block size follows a negative count
+                decode_long(input)?;
+            }
+            for _ in 0..count.abs() {
+                let key = String::from_utf8(decode_bytes(input)?).map_err(|err| err.to_string())?;
+                let value =
+                    String::from_utf8(decode_bytes(input)?).map_err(|err| err.to_string())?;
+                match key.as_str() {
+                    "avro.schema" => schema = Some(value),
+                    "avro.codec" => codec = Some(value),
+                    _ => {}
+                }
+            }
+        }
+        let mut sync = [0u8; SYNC_SIZE];
+        input.read_exact(&mut sync).map_err(|err| err.to_string())?;
+        Ok(Self {
+            schema: schema.ok_or("missing schema in avro header")?,
+            codec: codec.unwrap_or_else(|| Codec::Null.name().to_string()),
+            sync,
+        })
+    }
+
+    pub fn encode<W: Write + ?Sized>(&self, output: &mut W) -> io::Result<()> {
+        output.write_all(&OBJECT_CONTAINER_MAGIC)?;
+        encode_long(2, output)?;
+        encode_bytes(b"avro.schema", output)?;
+        encode_bytes(self.schema.as_bytes(), output)?;
+        encode_bytes(b"avro.codec", output)?;
+        encode_bytes(self.codec.as_bytes(), output)?;
+        encode_long(0, output)?;
+        output.write_all(&self.sync)
+    }
+}
+
+/// Framing of each record sent as a standalone message.
+enum Framing {
+    /// Avro single object encoding, prefixed by the schema fingerprint.
+    SingleObject(u64),
+    /// Confluent wire format, prefixed by the schema registry id.
+    Confluent(u32),
+}
+
+/// Formats each record as a standalone avro message.
+pub struct AvroFormatter {
+    schema: Schema,
+    framing: Framing,
+}
+
+impl AvroFormatter {
+    pub fn new(projection: &Projection) -> Result<Self, String> {
+        let schema = Schema::new(projection)?;
+        let framing = Framing::SingleObject(schema.fingerprint());
+        Ok(Self { schema, framing })
+    }
+
+    pub fn confluent(projection: &Projection, schema_id: u32) -> Result<Self, String> {
+        Ok(Self {
+            schema: Schema::new(projection)?,
+            framing: Framing::Confluent(schema_id),
+        })
+    }
+}
+
+impl AvroFormatter {
+    fn write(&self, event: &Record, output: &mut dyn Write) -> io::Result<()> {
+        match self.framing {
+            Framing::SingleObject(fingerprint) => {
+                output.write_all(&SINGLE_OBJECT_MARKER)?;
+                output.write_all(&fingerprint.to_le_bytes())?;
+            }
+            Framing::Confluent(schema_id) => {
+                output.write_all(&[CONFLUENT_MARKER])?;
+                output.write_all(&schema_id.to_be_bytes())?;
+            }
+        }
+        self.schema.encode(event, output)
+    }
+}
+
+impl Formatter for AvroFormatter {
+    fn format(&self, event: &Record, output: &mut dyn Write) -> Result<(), String> {
+        self.write(event, output).map_err(|err| err.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{encode_long, AvroFormatter, Codec, Header, Schema};
+    use crate::format::{Formatter, Projection};
+    use crate::model::{Field, NetworkInterface, NetworkUsage, Record};
+
+    #[test]
+    fn should_encode_longs_with_zigzag() {
+        for (value, expected) in [
+            (0, vec![0x00]),
+            (-1, vec![0x01]),
+            (1, vec![0x02]),
+            (-64, vec![0x7f]),
+            (64, vec![0x80, 0x01]),
+        ] {
+            let mut output = Vec::new();
+            encode_long(value, &mut output).unwrap();
+            assert_eq!(output, expected);
+        }
+    }
+
+    #[test]
+    fn should_build_canonical_schema() {
+        let projection =
+            Projection::new(vec![Field::ContainerName, Field::PidCount], Vec::new()).unwrap();
+        let schema = Schema::new(&projection).unwrap();
+        assert_eq!(
+            schema.to_canonical(),
+            r#"{"name":"docker_activity.Record","type":"record","fields":[{"name":"containerName","type":"string"},{"name":"pidCount","type":["null","long"]}]}"#
+        );
+    }
+
+    #[test]
+    fn should_build_canonical_nested_schema() {
+        let projection = Projection::new(vec![Field::Networks], Vec::new()).unwrap();
+        let schema = Schema::new(&projection).unwrap();
+        let canonical = schema.to_canonical();
+        assert!(canonical.starts_with(
+            r#"{"name":"docker_activity.Record","type":"record","fields":[{"name":"networks","type":{"type":"map","values":{"name":"docker_activity.NetworkInterface","type":"record","fields":[{"name":"rxBytes","type":"long"}"#
+        ));
+        assert!(canonical.ends_with(r#"{"name":"txBytesRate","type":["null","double"]}]}}}]}"#));
+    }
+
+    #[test]
+    fn should_encode_nested_values() {
+        let projection = Projection::new(vec![Field::Networks], Vec::new()).unwrap();
+        let formatter = AvroFormatter::confluent(&projection, 1).unwrap();
+        let mut record = Record::random();
+        record.networks.push(NetworkInterface {
+            name: "lo".into(),
+            usage: NetworkUsage {
+                rx_bytes: 1,
+                ..Default::default()
+            },
+            rates: None,
+        });
+        let mut output = Vec::new();
+        formatter.format(&record, &mut output).unwrap();
+        assert_eq!(
+            &output[5..],
+            &[0x02, 0x04, b'l', b'o', 0x02, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x00]
+        );
+    }
+
+    #[test]
+    fn should_encode_lists() {
+        let projection = Projection::new(vec![Field::CpuPerCore], Vec::new()).unwrap();
+        let formatter = AvroFormatter::confluent(&projection, 1).unwrap();
+        let mut record = Record::random();
+        record.cpu_per_core = Some(vec![1, 2]);
+        let mut output = Vec::new();
+        formatter.format(&record, &mut output).unwrap();
+        assert_eq!(&output[5..], &[0x02, 0x04, 0x02, 0x04, 0x00]);
+        assert!(Schema::new(&projection)
+            .unwrap()
+            .to_canonical()
+            .contains(r#"["null",{"type":"array","items":"long"}]"#));
+    }
+
+    #[test]
+    fn should_reject_invalid_names() {
+        let projection = Projection::new(
+            vec![Field::ContainerName],
+            vec!["containerName=container-name".parse().unwrap()],
+        )
+        .unwrap();
+        assert!(Schema::new(&projection).is_err());
+    }
+
+    #[test]
+    fn should_encode_single_object() {
+        let projection =
+            Projection::new(vec![Field::ContainerName, Field::MemoryLimit], Vec::new()).unwrap();
+        let formatter = AvroFormatter::new(&projection).unwrap();
+        let fingerprint = formatter.schema.fingerprint();
+        let mut output = Vec::new();
+        formatter.format(&Record::random(), &mut output).unwrap();
+        assert_eq!(&output[0..2], &[0xC3, 0x01]);
+        assert_eq!(&output[2..10], &fingerprint.to_le_bytes());
+        assert_eq!(&output[10..], &[0x0a, b'w', b'o', b'r', b'l', b'd', 0x00]);
+    }
+
+    #[test]
+    fn should_encode_confluent() {
+        let projection = Projection::new(vec![Field::PidCount], Vec::new()).unwrap();
+        let formatter = AvroFormatter::confluent(&projection, 42).unwrap();
+        let mut output = Vec::new();
+        formatter.format(&Record::random(), &mut output).unwrap();
+        assert_eq!(output, vec![0x00, 0x00, 0x00, 0x00, 42, 0x02, 24]);
+    }
+
+    #[test]
+    fn should_compress_with_snappy() {
+        let data = b"hello hello hello hello".to_vec();
+        let result = Codec::Snappy.compress(data.clone()).unwrap();
+        let (compressed, checksum) = result.split_at(result.len() - 4);
+        let decompressed = snap::raw::Decoder::new()
+            .decompress_vec(compressed)
+            .unwrap();
+        assert_eq!(decompressed, data);
+        assert_eq!(checksum, &crc32fast::hash(&data).to_be_bytes());
+    }
+
+    #[test]
+    fn should_read_written_header() {
+        let schema = Schema::new(&Projection::default()).unwrap();
+        let header = Header::new(&schema, Codec::Deflate);
+        let mut output = Vec::new();
+        header.encode(&mut output).unwrap();
+        let result = Header::read(&mut output.as_slice()).unwrap();
+        assert_eq!(result.schema, schema.to_json());
+        assert_eq!(result.codec, "deflate");
+        assert_eq!(result.sync, header.sync);
+    }
+}
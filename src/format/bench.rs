@@ -0,0 +1,112 @@
+//! Benchmarks of the formatters, run with `cargo bench --features bench`.
+//!
+//! The allocations are counted per thread, to make sure formatting a record
+//! in a reused buffer doesn't allocate once the buffer is large enough. As the
+//! counting allocator replaces the global one, it's only installed with the
+//! `bench` feature, leaving the other tests on the system allocator.
+
+extern crate test;
+
+use super::csv::CsvFormatter;
+#[cfg(feature = "formatter-json")]
+use super::json::JsonFormatter;
+use super::Formatter;
+use crate::model::Record;
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use test::Bencher;
+
+const RECORDS: usize = 1000;
+
+struct CountingAllocator;
+
+thread_local! {
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+fn count_allocation() {
+    let _ = ALLOCATIONS.try_with(|count| count.set(count.get() + 1));
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        count_allocation();
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        count_allocation();
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+fn allocations() -> usize {
+    ALLOCATIONS.with(|count| count.get())
+}
+
+/// Formats the records in the same buffer and returns the allocations per record.
+fn allocations_per_record(formatter: &dyn Formatter, records: &[Record]) -> f64 {
+    let mut buffer = Vec::with_capacity(4096);
+    // warm up to let the buffer reach its final capacity
+    formatter.format(&records[0], &mut buffer).unwrap();
+    let before = allocations();
+    for record in records {
+        buffer.clear();
+        formatter.format(record, &mut buffer).unwrap();
+    }
+    (allocations() - before) as f64 / records.len() as f64
+}
+
+fn bench_formatter(b: &mut Bencher, formatter: &dyn Formatter) {
+    let records: Vec<Record> = (0..RECORDS).map(|_| Record::random()).collect();
+    let mut buffer = Vec::with_capacity(4096);
+    formatter.format(&records[0], &mut buffer).unwrap();
+    b.bytes = (buffer.len() * RECORDS) as u64;
+    b.iter(|| {
+        for record in records.iter() {
+            buffer.clear();
+            formatter.format(record, &mut buffer).unwrap();
+            test::black_box(&buffer);
+        }
+    });
+}
+
+#[test]
+fn csv_should_not_allocate_per_record() {
+    let records: Vec<Record> = (0..RECORDS).map(|_| Record::random()).collect();
+    let formatter = CsvFormatter::default();
+    assert_eq!(allocations_per_record(&formatter, &records), 0.0);
+}
+
+#[cfg(feature = "formatter-json")]
+#[test]
+fn json_should_not_allocate_per_record() {
+    let records: Vec<Record> = (0..RECORDS).map(|_| Record::random()).collect();
+    let formatter = JsonFormatter::default();
+    assert_eq!(allocations_per_record(&formatter, &records), 0.0);
+}
+
+#[bench]
+fn csv_thousand_records(b: &mut Bencher) {
+    bench_formatter(b, &CsvFormatter::default());
+}
+
+#[cfg(feature = "formatter-json")]
+#[bench]
+fn json_thousand_records(b: &mut Bencher) {
+    bench_formatter(b, &JsonFormatter::default());
+}
+
+#[cfg(feature = "formatter-avro")]
+#[bench]
+fn avro_thousand_records(b: &mut Bencher) {
+    let projection = super::Projection::default();
+    bench_formatter(b, &super::avro::AvroFormatter::new(&projection).unwrap());
+}
@@ -1,41 +1,172 @@
-use super::Formatter;
-use crate::model::Record;
+use super::{Formatter, Projection};
+use crate::model::{Record, Value};
+use std::convert::TryFrom;
+use std::io::{self, Write};
 
-pub struct CsvFormatter;
+/// Writes the values of the records without header, so the fields can't be renamed.
+#[derive(Default)]
+pub struct CsvFormatter(Projection);
 
-#[cfg(feature = "enrichment-powercap")]
-impl Formatter for CsvFormatter {
-    fn format(&self, event: Record) -> Result<String, String> {
-        Ok(format!(
-            "{},{},{},{},{},{},{},{},{},{}",
-            event.container_id,
-            event.container_name,
-            event.ts,
-            event.pid_count.unwrap_or_default(),
-            event.pid_limit.unwrap_or_default(),
-            event.memory_usage.unwrap_or_default(),
-            event.memory_limit.unwrap_or_default(),
-            event.cpu_percent,
-            event.cpu_count,
-            event.cpu_energy.unwrap_or_default(),
-        ))
+impl TryFrom<Projection> for CsvFormatter {
+    type Error = String;
+
+    fn try_from(projection: Projection) -> Result<Self, Self::Error> {
+        match projection.is_renamed() {
+            true => {
+                Err("the fields can't be renamed in the csv format, which has no header".into())
+            }
+            false => Ok(Self(projection)),
+        }
+    }
+}
+
+fn write_value(output: &mut dyn Write, value: &Value) -> io::Result<()> {
+    match value {
+        Value::Null => Ok(()),
+        Value::Boolean(value) => write!(output, "{}", value),
+        Value::Integer(value) => write!(output, "{}", value),
+        Value::Unsigned(value) => write!(output, "{}", value),
+        Value::Float(value) => write!(output, "{}", value),
+        Value::Text(value) if value.contains(&[',', '"', '\n', '\r'][..]) => {
+            write!(output, "\"{}\"", value.replace('"', "\"\""))
+        }
+        Value::Text(value) => output.write_all(value.as_bytes()),
+        Value::List(_) | Value::Map(_) => {
+            output.write_all(b"\"")?;
+            write_nested(output, value)?;
+            output.write_all(b"\"")
+        }
+    }
+}
+
+/// Writes a nested value as JSON inside a quoted cell, the quotes being doubled.
+fn write_nested(output: &mut dyn Write, value: &Value) -> io::Result<()> {
+    match value {
+        Value::Null => output.write_all(b"null"),
+        Value::Text(value) => write_nested_text(output, value),
+        Value::List(items) => {
+            output.write_all(b"[")?;
+            for (index, item) in items.iter().enumerate() {
+                if index > 0 {
+                    output.write_all(b",")?;
+                }
+                write_nested(output, item)?;
+            }
+            output.write_all(b"]")
+        }
+        Value::Map(entries) => {
+            output.write_all(b"{")?;
+            for (index, (key, value)) in entries.iter().enumerate() {
+                if index > 0 {
+                    output.write_all(b",")?;
+                }
+                write_nested_text(output, key)?;
+                output.write_all(b":")?;
+                write_nested(output, value)?;
+            }
+            output.write_all(b"}")
+        }
+        other => write_value(output, other),
+    }
+}
+
+fn write_nested_text(output: &mut dyn Write, value: &str) -> io::Result<()> {
+    output.write_all(b"\"\"")?;
+    let mut start = 0;
+    for (index, special) in value.match_indices(&['"', '\\'][..]) {
+        output.write_all(&value.as_bytes()[start..index])?;
+        match special {
+            "\"" => output.write_all(b"\\\"\"")?,
+            _ => output.write_all(b"\\\\")?,
+        }
+        start = index + special.len();
+    }
+    output.write_all(&value.as_bytes()[start..])?;
+    output.write_all(b"\"\"")
+}
+
+impl CsvFormatter {
+    fn write(&self, event: &Record, output: &mut dyn Write) -> io::Result<()> {
+        for (index, (_, value)) in self.0.entries(event).enumerate() {
+            if index > 0 {
+                output.write_all(b",")?;
+            }
+            write_value(output, &value)?;
+        }
+        output.write_all(super::LINE_ENDING.as_bytes())
     }
 }
 
-#[cfg(not(feature = "enrichment-powercap"))]
 impl Formatter for CsvFormatter {
-    fn format(&self, event: Record) -> Result<String, String> {
-        Ok(format!(
-            "{},{},{},{},{},{},{},{},{}",
-            event.container_id,
-            event.container_name,
-            event.ts,
-            event.pid_count.unwrap_or_default(),
-            event.pid_limit.unwrap_or_default(),
-            event.memory_usage.unwrap_or_default(),
-            event.memory_limit.unwrap_or_default(),
-            event.cpu_percent,
-            event.cpu_count,
-        ))
+    fn format(&self, event: &Record, output: &mut dyn Write) -> Result<(), String> {
+        self.write(event, output).map_err(|err| err.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CsvFormatter;
+    use crate::format::{Formatter, Projection, Rename};
+    use crate::model::{Field, NetworkInterface, NetworkUsage, Record};
+    use std::convert::TryFrom;
+    use std::str::FromStr;
+
+    #[test]
+    fn should_only_export_selected_fields() {
+        let formatter = CsvFormatter::try_from(
+            Projection::new(
+                vec![Field::ContainerName, Field::MemoryLimit, Field::PidCount],
+                Vec::new(),
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        let mut record = Record::random();
+        record.container_name = "with,comma".into();
+        let mut result = Vec::new();
+        formatter.format(&record, &mut result).unwrap();
+        assert_eq!(result, b"\"with,comma\",,12\n");
+    }
+
+    #[test]
+    fn should_export_lists_as_json() {
+        let formatter = CsvFormatter::try_from(
+            Projection::new(vec![Field::CpuPerCore, Field::CpuUserTime], Vec::new()).unwrap(),
+        )
+        .unwrap();
+        let mut record = Record::random();
+        record.cpu_per_core = Some(vec![1, 2]);
+        let mut result = Vec::new();
+        formatter.format(&record, &mut result).unwrap();
+        assert_eq!(result, b"\"[1,2]\",600\n");
+    }
+
+    #[test]
+    fn should_export_nested_values_as_json() {
+        let formatter =
+            CsvFormatter::try_from(Projection::new(vec![Field::Networks], Vec::new()).unwrap())
+                .unwrap();
+        let mut record = Record::random();
+        record.networks.push(NetworkInterface {
+            name: "eth\"0".into(),
+            usage: NetworkUsage::default(),
+            rates: None,
+        });
+        let mut result = Vec::new();
+        formatter.format(&record, &mut result).unwrap();
+        assert_eq!(
+            String::from_utf8(result).unwrap(),
+            "\"{\"\"eth\\\"\"0\"\":{\"\"rxBytes\"\":0,\"\"rxPackets\"\":0,\"\"rxErrors\"\":0,\"\"rxDropped\"\":0,\"\"txBytes\"\":0,\"\"txPackets\"\":0,\"\"txErrors\"\":0,\"\"txDropped\"\":0,\"\"rxBytesRate\"\":null,\"\"txBytesRate\"\":null}}\"\n"
+        );
+    }
+
+    #[test]
+    fn should_reject_renamed_fields() {
+        let projection = Projection::new(
+            vec![Field::ContainerName],
+            vec![Rename::from_str("containerName=name").unwrap()],
+        )
+        .unwrap();
+        assert!(CsvFormatter::try_from(projection).is_err());
     }
 }
@@ -1,10 +1,88 @@
-use super::Formatter;
+use super::{Formatter, Projection};
 use crate::model::Record;
+use serde::ser::{Serialize, SerializeMap, Serializer};
+use std::io::Write;
 
-pub struct JsonFormatter;
+#[derive(Default)]
+pub struct JsonFormatter(Projection);
+
+impl From<Projection> for JsonFormatter {
+    fn from(projection: Projection) -> Self {
+        Self(projection)
+    }
+}
+
+struct Projected<'a> {
+    projection: &'a Projection,
+    record: &'a Record,
+}
+
+impl Serialize for Projected<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(None)?;
+        for (name, value) in self.projection.entries(self.record) {
+            map.serialize_entry(name, &value)?;
+        }
+        map.end()
+    }
+}
 
 impl Formatter for JsonFormatter {
-    fn format(&self, event: Record) -> Result<String, String> {
-        serde_json::to_string(&event).map_err(|err| err.to_string())
+    fn format(&self, event: &Record, output: &mut dyn Write) -> Result<(), String> {
+        let projected = Projected {
+            projection: &self.0,
+            record: event,
+        };
+        serde_json::to_writer(&mut *output, &projected).map_err(|err| err.to_string())?;
+        output
+            .write_all(super::LINE_ENDING.as_bytes())
+            .map_err(|err| err.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::JsonFormatter;
+    use crate::format::{Formatter, Projection, Rename};
+    use crate::model::{Field, NetworkInterface, NetworkRates, NetworkUsage, Record};
+    use std::str::FromStr;
+
+    #[test]
+    fn should_only_export_selected_fields() {
+        let formatter = JsonFormatter::from(
+            Projection::new(
+                vec![Field::PidCount, Field::ContainerName],
+                vec![Rename::from_str("pidCount=pids").unwrap()],
+            )
+            .unwrap(),
+        );
+        let mut result = Vec::new();
+        formatter.format(&Record::random(), &mut result).unwrap();
+        assert_eq!(result, b"{\"pids\":12,\"containerName\":\"world\"}\n");
+    }
+
+    #[test]
+    fn should_export_nested_values() {
+        let formatter = JsonFormatter::from(
+            Projection::new(vec![Field::NetworkRxBytes, Field::Networks], Vec::new()).unwrap(),
+        );
+        let mut record = Record::random();
+        record.networks.push(NetworkInterface {
+            name: "eth0".into(),
+            usage: NetworkUsage {
+                tx_bytes: 12,
+                ..Default::default()
+            },
+            rates: Some(NetworkRates {
+                tx_bytes: 1.5,
+                ..Default::default()
+            }),
+        });
+        let mut result = Vec::new();
+        formatter.format(&record, &mut result).unwrap();
+        let result: serde_json::Value = serde_json::from_slice(&result).unwrap();
+        assert_eq!(result["networkRxBytes"], 1024);
+        assert_eq!(result["networks"]["eth0"]["txBytes"], 12);
+        assert_eq!(result["networks"]["eth0"]["txBytesRate"], 1.5);
     }
 }
@@ -1,36 +1,43 @@
+#[cfg(feature = "formatter-avro")]
+pub(crate) mod avro;
+#[cfg(all(test, feature = "bench"))]
+mod bench;
 pub(crate) mod csv;
 #[cfg(feature = "formatter-json")]
 pub(crate) mod json;
+mod projection;
+mod units;
 
 use crate::model::Record;
+use std::convert::TryFrom;
+use std::io::Write;
 use std::str::FromStr;
 
-#[derive(Debug, Clone)]
+pub use projection::{Projection, Rename};
+#[cfg(feature = "enrichment-powercap")]
+pub use units::EnergyUnit;
+pub use units::{CpuUnit, MemoryUnit, TimeUnit, Units};
+
+pub const LINE_ENDING: &str = "\n";
+
+#[derive(Debug, Clone, Default)]
 pub enum Format {
+    #[cfg(feature = "formatter-avro")]
+    Avro,
     #[cfg(feature = "formatter-json")]
+    #[default]
     Json,
+    #[cfg_attr(not(feature = "formatter-json"), default)]
     Csv,
 }
 
-#[cfg(not(feature = "formatter-json"))]
-impl Default for Format {
-    fn default() -> Self {
-        Self::Csv
-    }
-}
-
-#[cfg(feature = "formatter-json")]
-impl Default for Format {
-    fn default() -> Self {
-        Self::Json
-    }
-}
-
 impl FromStr for Format {
     type Err = String;
 
     fn from_str(input: &str) -> Result<Self, Self::Err> {
         match input {
+            #[cfg(feature = "formatter-avro")]
+            "avro" => Ok(Self::Avro),
             #[cfg(feature = "formatter-json")]
             "json" => Ok(Self::Json),
             "csv" => Ok(Self::Csv),
@@ -40,15 +47,22 @@ impl FromStr for Format {
 }
 
 impl Format {
-    pub fn formatter(&self) -> Box<dyn Formatter> {
+    pub fn formatter(&self, projection: Projection) -> Box<dyn Formatter> {
         match self {
-            Self::Csv => Box::new(csv::CsvFormatter),
+            #[cfg(feature = "formatter-avro")]
+            Self::Avro => {
+                Box::new(avro::AvroFormatter::new(&projection).expect("invalid avro schema"))
+            }
+            Self::Csv => {
+                Box::new(csv::CsvFormatter::try_from(projection).expect("invalid csv projection"))
+            }
             #[cfg(feature = "formatter-json")]
-            Self::Json => Box::new(json::JsonFormatter),
+            Self::Json => Box::new(json::JsonFormatter::from(projection)),
         }
     }
 }
 
 pub trait Formatter {
-    fn format(&self, event: Record) -> Result<String, String>;
+    /// Writes the record in the output, including its delimiter when the format requires one.
+    fn format(&self, event: &Record, output: &mut dyn Write) -> Result<(), String>;
 }
@@ -0,0 +1,198 @@
+use super::units::Units;
+#[cfg(feature = "formatter-avro")]
+use crate::model::Kind;
+use crate::model::{Field, Record, Value};
+use std::str::FromStr;
+
+/// Renaming of a field in the output, written `field=name`.
+#[derive(Debug, Clone)]
+pub struct Rename {
+    pub field: Field,
+    pub name: String,
+}
+
+impl FromStr for Rename {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let (field, name) = input
+            .split_once('=')
+            .ok_or_else(|| format!("invalid rename {:?}, expected field=name", input))?;
+        let name = name.trim();
+        if name.is_empty() {
+            return Err(format!("invalid rename {:?}, the name is empty", input));
+        }
+        Ok(Self {
+            field: Field::from_str(field.trim())?,
+            name: name.to_string(),
+        })
+    }
+}
+
+/// Fields to output, in order, with the name they should have.
+#[derive(Debug, Clone)]
+pub struct Projection {
+    fields: Vec<(Field, String)>,
+    units: Units,
+}
+
+impl Default for Projection {
+    fn default() -> Self {
+        Self {
+            fields: Field::ALL
+                .iter()
+                .map(|field| (*field, field.name().to_string()))
+                .collect(),
+            units: Units::default(),
+        }
+    }
+}
+
+impl Projection {
+    /// Builds a projection from the selected fields and the renamings to apply.
+    /// No selected field means that every field is exported. Selecting or
+    /// renaming a field twice, or giving the same name to two fields, is an
+    /// error as the output would have duplicate keys.
+    pub fn new(fields: Vec<Field>, renames: Vec<Rename>) -> Result<Self, String> {
+        let fields = if fields.is_empty() {
+            Field::ALL.to_vec()
+        } else {
+            fields
+        };
+        for (index, field) in fields.iter().enumerate() {
+            if fields[..index].contains(field) {
+                return Err(format!("the field {:?} is selected twice", field.name()));
+            }
+        }
+        for (index, rename) in renames.iter().enumerate() {
+            if renames[..index]
+                .iter()
+                .any(|other| other.field == rename.field)
+            {
+                return Err(format!(
+                    "the field {:?} is renamed twice",
+                    rename.field.name()
+                ));
+            }
+        }
+        let fields: Vec<_> = fields
+            .into_iter()
+            .map(|field| {
+                let name = renames
+                    .iter()
+                    .find(|rename| rename.field == field)
+                    .map(|rename| rename.name.clone())
+                    .unwrap_or_else(|| field.name().to_string());
+                (field, name)
+            })
+            .collect();
+        for (index, (_, name)) in fields.iter().enumerate() {
+            if fields[..index].iter().any(|(_, other)| other == name) {
+                return Err(format!("two fields are named {:?}", name));
+            }
+        }
+        Ok(Self {
+            fields,
+            units: Units::default(),
+        })
+    }
+
+    /// Whether some of the fields don't have their default name.
+    pub fn is_renamed(&self) -> bool {
+        self.fields
+            .iter()
+            .any(|(field, name)| field.name() != name.as_str())
+    }
+
+    pub fn with_units(mut self, units: Units) -> Self {
+        self.units = units;
+        self
+    }
+
+    #[cfg(feature = "formatter-avro")]
+    pub fn units(&self) -> &Units {
+        &self.units
+    }
+
+    /// Selected fields with their output names and the type of their values.
+    #[cfg(feature = "formatter-avro")]
+    pub fn fields(&self) -> impl Iterator<Item = (Field, &str, Kind)> {
+        self.fields
+            .iter()
+            .map(move |(field, name)| (*field, name.as_str(), self.units.kind(*field)))
+    }
+
+    /// Output names of the selected fields.
+    #[cfg(test)]
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.fields.iter().map(|(_, name)| name.as_str())
+    }
+
+    /// Output names and values, in the expected units, of the selected fields for the given record.
+    pub fn entries<'a>(&'a self, record: &'a Record) -> impl Iterator<Item = (&'a str, Value<'a>)> {
+        self.fields
+            .iter()
+            .map(move |(field, name)| (name.as_str(), self.units.value(record, *field)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Projection, Rename};
+    use crate::model::Field;
+    use std::str::FromStr;
+
+    #[test]
+    fn should_keep_order_and_rename() {
+        let projection = Projection::new(
+            vec![Field::MemoryUsage, Field::ContainerName],
+            vec![Rename::from_str("container_name=name").unwrap()],
+        )
+        .unwrap();
+        let names: Vec<_> = projection.names().collect();
+        assert_eq!(names, vec!["memoryUsage", "name"]);
+        assert!(projection.is_renamed());
+    }
+
+    #[test]
+    fn should_select_everything_by_default() {
+        let projection = Projection::new(Vec::new(), Vec::new()).unwrap();
+        assert_eq!(projection.names().count(), Field::ALL.len());
+        assert!(!projection.is_renamed());
+        assert_eq!(
+            Projection::default().names().collect::<Vec<_>>(),
+            projection.names().collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn should_reject_duplicate_keys() {
+        let rename = |value: &str| Rename::from_str(value).unwrap();
+        assert!(Projection::new(vec![Field::Ts, Field::PidCount, Field::Ts], Vec::new()).is_err());
+        assert!(Projection::new(
+            vec![Field::Ts],
+            vec![rename("ts=time"), rename("ts=timestamp")]
+        )
+        .is_err());
+        assert!(Projection::new(
+            vec![Field::Ts, Field::PidCount],
+            vec![rename("ts=value"), rename("pidCount=value")]
+        )
+        .is_err());
+        // renamed like another field
+        assert!(Projection::new(
+            vec![Field::Ts, Field::PidCount],
+            vec![rename("ts=pidCount")]
+        )
+        .is_err());
+        // the other field isn't selected
+        assert!(Projection::new(vec![Field::Ts], vec![rename("ts=pidCount")]).is_ok());
+    }
+
+    #[test]
+    fn should_reject_invalid_rename() {
+        assert!(Rename::from_str("containerName").is_err());
+        assert!(Rename::from_str("containerName=").is_err());
+        assert!(Rename::from_str("unknown=name").is_err());
+    }
+}
@@ -0,0 +1,297 @@
+#[cfg(feature = "formatter-avro")]
+use crate::model::Kind;
+#[cfg(feature = "enrichment-powercap")]
+use crate::model::MICROJOULES_PER_JOULE;
+use crate::model::{Field, Quantity, Record, Value};
+use chrono::SecondsFormat;
+use std::borrow::Cow;
+use std::str::FromStr;
+
+const KIBIBYTE: f64 = 1024.0;
+const MEBIBYTE: f64 = 1024.0 * 1024.0;
+#[cfg(feature = "enrichment-powercap")]
+const JOULES_PER_WATT_HOUR: f64 = 3_600.0;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MemoryUnit {
+    #[default]
+    Bytes,
+    Kibibytes,
+    Mebibytes,
+}
+
+impl FromStr for MemoryUnit {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        match input.to_lowercase().as_str() {
+            "b" | "bytes" => Ok(Self::Bytes),
+            "kib" => Ok(Self::Kibibytes),
+            "mib" => Ok(Self::Mebibytes),
+            _ => Err(format!("unknown memory unit {:?}", input)),
+        }
+    }
+}
+
+#[cfg(feature = "enrichment-powercap")]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum EnergyUnit {
+    #[default]
+    Microjoules,
+    Joules,
+    WattHours,
+    KilowattHours,
+}
+
+#[cfg(feature = "enrichment-powercap")]
+impl FromStr for EnergyUnit {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        match input.to_lowercase().as_str() {
+            "uj" | "microjoules" => Ok(Self::Microjoules),
+            "j" | "joules" => Ok(Self::Joules),
+            "wh" => Ok(Self::WattHours),
+            "kwh" => Ok(Self::KilowattHours),
+            _ => Err(format!("unknown energy unit {:?}", input)),
+        }
+    }
+}
+
+#[cfg(feature = "enrichment-powercap")]
+impl EnergyUnit {
+    /// Converts an amount of energy from micro joules.
+    pub fn convert(&self, value: f64) -> f64 {
+        let joules = value / MICROJOULES_PER_JOULE;
+        match self {
+            Self::Microjoules => value,
+            Self::Joules => joules,
+            Self::WattHours => joules / JOULES_PER_WATT_HOUR,
+            Self::KilowattHours => joules / JOULES_PER_WATT_HOUR / 1_000.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CpuUnit {
+    /// Share of the whole host, between 0 and 1.
+    #[default]
+    Ratio,
+    /// Share of the whole host, between 0 and 100.
+    Percent,
+    /// Percentage of one core, between 0 and 100 times the number of cores.
+    CorePercent,
+}
+
+impl FromStr for CpuUnit {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        match input.to_lowercase().as_str() {
+            "ratio" => Ok(Self::Ratio),
+            "percent" => Ok(Self::Percent),
+            "core-percent" => Ok(Self::CorePercent),
+            _ => Err(format!("unknown cpu unit {:?}", input)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TimeUnit {
+    #[default]
+    Seconds,
+    Milliseconds,
+    Nanoseconds,
+    Rfc3339,
+}
+
+impl FromStr for TimeUnit {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        match input.to_lowercase().as_str() {
+            "s" | "seconds" => Ok(Self::Seconds),
+            "ms" | "milliseconds" => Ok(Self::Milliseconds),
+            "ns" | "nanoseconds" => Ok(Self::Nanoseconds),
+            "rfc3339" => Ok(Self::Rfc3339),
+            _ => Err(format!("unknown time unit {:?}", input)),
+        }
+    }
+}
+
+/// Units in which the values of the fields are exported.
+#[derive(Debug, Clone, Default)]
+pub struct Units {
+    pub memory: MemoryUnit,
+    #[cfg(feature = "enrichment-powercap")]
+    pub energy: EnergyUnit,
+    pub cpu: CpuUnit,
+    pub time: TimeUnit,
+}
+
+impl Units {
+    /// Value of the field for the given record, converted in the expected unit.
+    pub fn value<'a>(&self, record: &'a Record, field: Field) -> Value<'a> {
+        match (field.quantity(), record.get(field)) {
+            (Some(Quantity::Time), _) => match (record.timestamp(field), self.time) {
+                (None, _) => Value::Null,
+                (Some(ts), TimeUnit::Seconds) => Value::Integer(ts.timestamp()),
+                (Some(ts), TimeUnit::Milliseconds) => Value::Integer(ts.timestamp_millis()),
+                // out of the years 1677 to 2262
+                (Some(ts), TimeUnit::Nanoseconds) => {
+                    ts.timestamp_nanos_opt().map_or(Value::Null, Value::Integer)
+                }
+                (Some(ts), TimeUnit::Rfc3339) => {
+                    Value::Text(Cow::Owned(ts.to_rfc3339_opts(SecondsFormat::AutoSi, true)))
+                }
+            },
+            (Some(Quantity::Memory), Value::Unsigned(value)) => match self.memory {
+                MemoryUnit::Bytes => Value::Unsigned(value),
+                MemoryUnit::Kibibytes => Value::Float(value as f64 / KIBIBYTE),
+                MemoryUnit::Mebibytes => Value::Float(value as f64 / MEBIBYTE),
+            },
+            #[cfg(feature = "enrichment-powercap")]
+            (Some(Quantity::Energy), Value::Float(value)) => {
+                Value::Float(self.energy.convert(value))
+            }
+            #[cfg(feature = "enrichment-powercap")]
+            (Some(Quantity::Energy), Value::Map(entries)) => Value::Map(
+                entries
+                    .into_iter()
+                    .map(|(key, value)| match value {
+                        Value::Float(value) => (key, Value::Float(self.energy.convert(value))),
+                        other => (key, other),
+                    })
+                    .collect(),
+            ),
+            (Some(Quantity::Cpu), Value::Float(value)) => match self.cpu {
+                CpuUnit::Ratio => Value::Float(value),
+                CpuUnit::Percent => Value::Float(value * 100.0),
+                CpuUnit::CorePercent => Value::Float(value * record.cpu_count as f64 * 100.0),
+            },
+            (_, value) => value,
+        }
+    }
+
+    /// Type of the values of the field, once converted.
+    #[cfg(feature = "formatter-avro")]
+    pub fn kind(&self, field: Field) -> Kind {
+        match field.quantity() {
+            Some(Quantity::Time) if self.time == TimeUnit::Rfc3339 => Kind::Text,
+            Some(Quantity::Memory) if self.memory != MemoryUnit::Bytes => Kind::Float,
+            _ => field.kind(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(feature = "enrichment-powercap")]
+    use super::EnergyUnit;
+    use super::{CpuUnit, MemoryUnit, TimeUnit, Units};
+    use crate::model::{ContainerMetadata, Field, Record, Value};
+    use chrono::{TimeZone, Utc};
+    use std::borrow::Cow;
+    use std::sync::Arc;
+
+    #[test]
+    fn should_keep_raw_values_by_default() {
+        let record = Record::random();
+        let units = Units::default();
+        assert_eq!(units.value(&record, Field::Ts), Value::Integer(1234));
+        assert_eq!(
+            units.value(&record, Field::MemoryUsage),
+            Value::Unsigned(14)
+        );
+        assert_eq!(units.value(&record, Field::CpuPercent), Value::Float(0.89));
+    }
+
+    #[test]
+    fn should_convert_values() {
+        let mut record = Record::random();
+        record.memory_usage = Some(3 * 1024 * 1024);
+        let units = Units {
+            memory: MemoryUnit::Mebibytes,
+            #[cfg(feature = "enrichment-powercap")]
+            energy: EnergyUnit::WattHours,
+            cpu: CpuUnit::CorePercent,
+            time: TimeUnit::Rfc3339,
+        };
+        assert_eq!(
+            units.value(&record, Field::Ts),
+            Value::Text(Cow::Borrowed("1970-01-01T00:20:34Z"))
+        );
+        assert_eq!(units.value(&record, Field::MemoryUsage), Value::Float(3.0));
+        assert_eq!(units.value(&record, Field::MemoryLimit), Value::Null);
+        match units.value(&record, Field::CpuPercent) {
+            Value::Float(value) => assert!((value - 178.0).abs() < 1e-9),
+            other => panic!("unexpected value {:?}", other),
+        }
+    }
+
+    #[test]
+    fn should_convert_every_timestamp() {
+        let mut record = Record::random();
+        let units = Units {
+            time: TimeUnit::Milliseconds,
+            ..Default::default()
+        };
+        assert_eq!(units.value(&record, Field::CreatedAt), Value::Null);
+        record.metadata = Some(Arc::new(ContainerMetadata {
+            created_at: Some(Utc.timestamp_opt(12, 0).unwrap()),
+            ..Default::default()
+        }));
+        assert_eq!(
+            units.value(&record, Field::CreatedAt),
+            Value::Integer(12_000)
+        );
+        assert_eq!(units.value(&record, Field::Ts), Value::Integer(1_234_000));
+        let units = Units {
+            time: TimeUnit::Nanoseconds,
+            ..Default::default()
+        };
+        assert_eq!(
+            units.value(&record, Field::Ts),
+            Value::Integer(1_234_000_000_000)
+        );
+        // too far to be counted in nanoseconds
+        record.ts = Utc.with_ymd_and_hms(2300, 1, 1, 0, 0, 0).unwrap();
+        assert_eq!(units.value(&record, Field::Ts), Value::Null);
+    }
+
+    #[cfg(feature = "enrichment-powercap")]
+    #[test]
+    fn should_convert_energy() {
+        assert_eq!(EnergyUnit::KilowattHours.convert(3.6e12), 1.0);
+        assert_eq!(EnergyUnit::Joules.convert(2.5e6), 2.5);
+        assert_eq!(
+            "kWh".parse::<EnergyUnit>().unwrap(),
+            EnergyUnit::KilowattHours
+        );
+    }
+
+    #[cfg(feature = "enrichment-powercap")]
+    #[test]
+    fn should_convert_energy_of_every_zone() {
+        let mut record = Record::random();
+        record.energy_zones = vec![crate::model::EnergyZone {
+            name: "package-0".into(),
+            energy: 2.5e6,
+        }];
+        let units = Units {
+            energy: EnergyUnit::Joules,
+            ..Default::default()
+        };
+        assert_eq!(
+            units.value(&record, Field::EnergyZones),
+            Value::Map(vec![(Cow::Borrowed("package-0"), Value::Float(2.5))])
+        );
+    }
+
+    #[test]
+    fn should_parse_units() {
+        assert_eq!("MiB".parse::<MemoryUnit>().unwrap(), MemoryUnit::Mebibytes);
+        assert_eq!("ms".parse::<TimeUnit>().unwrap(), TimeUnit::Milliseconds);
+        assert!("whatever".parse::<CpuUnit>().is_err());
+    }
+}
@@ -0,0 +1,384 @@
+#[cfg(feature = "enrichment-sensors")]
+use crate::enrichment::sensors::{PowerSensor, Reading};
+use crate::enrichment::EnrichmentBuilder;
+#[cfg(feature = "enrichment-powercap")]
+use crate::enrichment::{powercap, EnergyLedger, EnergySource, StaticPolicy};
+#[cfg(feature = "enrichment-sensors")]
+use crate::model::EnergyAnomaly;
+use crate::model::{Message, Record, RecordType};
+use chrono::{DateTime, Utc};
+use std::time::Duration;
+use tokio::sync::{mpsc, watch};
+
+const PROC_STAT: &str = "/proc/stat";
+const PROC_MEMINFO: &str = "/proc/meminfo";
+const PROC_LOADAVG: &str = "/proc/loadavg";
+const KIBIBYTE: u64 = 1024;
+
+/// Time spent by all the cores, in clock ticks, since the boot.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct CpuTimes {
+    busy: u64,
+    total: u64,
+}
+
+impl CpuTimes {
+    /// Reads the aggregated `cpu` line of `/proc/stat`.
+    fn parse(input: &str) -> Option<Self> {
+        let line = input.lines().find(|line| line.starts_with("cpu "))?;
+        let values = line
+            .split_whitespace()
+            .skip(1)
+            .map(|value| value.parse::<u64>().ok())
+            .collect::<Option<Vec<_>>>()?;
+        // guest and guest_nice are already included in user and nice
+        let total: u64 = values.iter().take(8).sum();
+        // idle and iowait
+        let idle = values.get(3)? + values.get(4).copied().unwrap_or_default();
+        Some(Self {
+            busy: total - idle,
+            total,
+        })
+    }
+
+    /// Share of the time the cores were busy since the previous times.
+    fn share(&self, previous: &Self) -> Option<f64> {
+        let total = self.total.checked_sub(previous.total)?;
+        let busy = self.busy.checked_sub(previous.busy)?;
+        (total > 0).then(|| busy as f64 / total as f64)
+    }
+}
+
+/// Number of cores listed in `/proc/stat`.
+fn parse_cpu_count(input: &str) -> u64 {
+    input
+        .lines()
+        .filter(|line| {
+            line.strip_prefix("cpu")
+                .and_then(|rest| rest.chars().next())
+                .map(|first| first.is_ascii_digit())
+                .unwrap_or(false)
+        })
+        .count() as u64
+}
+
+/// Total and available memory, in bytes, read from `/proc/meminfo`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryInfo {
+    pub total: u64,
+    pub available: u64,
+}
+
+impl MemoryInfo {
+    pub fn read() -> Option<Self> {
+        read_proc(PROC_MEMINFO).as_deref().and_then(Self::parse)
+    }
+
+    fn parse(input: &str) -> Option<Self> {
+        let read = |name: &str| {
+            input
+                .lines()
+                .find_map(|line| line.strip_prefix(name))
+                .and_then(|rest| rest.trim_start_matches(':').split_whitespace().next())
+                .and_then(|value| value.parse::<u64>().ok())
+                .map(|value| value * KIBIBYTE)
+        };
+        Some(Self {
+            total: read("MemTotal")?,
+            available: read("MemAvailable")?,
+        })
+    }
+}
+
+/// Load averages over 1, 5 and 15 minutes, read from `/proc/loadavg`.
+fn parse_load_average(input: &str) -> Option<[f64; 3]> {
+    let mut values = input.split_whitespace().map(|value| value.parse().ok());
+    Some([values.next()??, values.next()??, values.next()??])
+}
+
+fn read_proc(path: &str) -> Option<String> {
+    match std::fs::read_to_string(path) {
+        Ok(content) => Some(content),
+        Err(error) => {
+            tracing::debug!("unable to read {}: {:?}", path, error);
+            None
+        }
+    }
+}
+
+/// Periodically samples the usage of the whole host and its energy, which is
+/// split between the watched containers at each tick.
+pub struct HostSampler {
+    interval: Duration,
+    /// Whether the records of the host are exported.
+    emit: bool,
+    previous: Option<(DateTime<Utc>, CpuTimes)>,
+    #[cfg(feature = "enrichment-powercap")]
+    energy: Option<EnergySource>,
+    #[cfg(feature = "enrichment-powercap")]
+    ledger: EnergyLedger,
+    #[cfg(feature = "enrichment-powercap")]
+    last_energy: Option<(DateTime<Utc>, Vec<powercap::ZoneCounter>)>,
+    #[cfg(feature = "enrichment-sensors")]
+    sensor: Option<PowerSensor>,
+    #[cfg(feature = "enrichment-sensors")]
+    last_reading: Option<Reading>,
+    /// Energy of the packages since the start, in micro joules.
+    #[cfg(feature = "enrichment-powercap")]
+    cpu_energy_total: f64,
+    /// Policy splitting the static energy, with the idle power of the power model.
+    #[cfg(feature = "enrichment-powercap")]
+    static_power: Option<(StaticPolicy, Option<f64>)>,
+    /// Lowest power of the packages seen so far, in watts.
+    #[cfg(feature = "enrichment-powercap")]
+    lowest_power: Option<f64>,
+}
+
+impl HostSampler {
+    /// Sampler sharing the energy reader of the enrichers. Without exporting the
+    /// records of the host, it's only needed to attribute the energy.
+    #[cfg_attr(not(feature = "enrichment-powercap"), allow(unused_variables))]
+    pub fn new(interval: Duration, enrichment: &EnrichmentBuilder, emit: bool) -> Option<Self> {
+        #[cfg(feature = "enrichment-powercap")]
+        let (energy, ledger) = match enrichment.energy() {
+            Some((source, ledger)) => (Some(source), ledger),
+            None => (None, EnergyLedger::default()),
+        };
+        #[cfg(feature = "enrichment-powercap")]
+        let needed = emit || energy.is_some();
+        #[cfg(not(feature = "enrichment-powercap"))]
+        let needed = emit;
+        needed.then_some(Self {
+            interval,
+            emit,
+            previous: None,
+            #[cfg(feature = "enrichment-powercap")]
+            energy,
+            #[cfg(feature = "enrichment-powercap")]
+            ledger,
+            #[cfg(feature = "enrichment-powercap")]
+            last_energy: None,
+            #[cfg(feature = "enrichment-sensors")]
+            sensor: enrichment.sensor(),
+            #[cfg(feature = "enrichment-sensors")]
+            last_reading: None,
+            #[cfg(feature = "enrichment-powercap")]
+            cpu_energy_total: 0.0,
+            #[cfg(feature = "enrichment-powercap")]
+            static_power: enrichment.static_power(),
+            #[cfg(feature = "enrichment-powercap")]
+            lowest_power: None,
+        })
+    }
+
+    fn sample(&mut self, ts: DateTime<Utc>) -> Record {
+        let mut record = Record::new(String::new(), String::new(), ts);
+        record.record_type = RecordType::Host;
+        let stat = read_proc(PROC_STAT);
+        if let Some(ref stat) = stat {
+            record.cpu_count = parse_cpu_count(stat);
+        }
+        let times = stat.as_deref().and_then(CpuTimes::parse);
+        if let (Some((last_ts, last_times)), Some(times)) = (self.previous, times) {
+            record.sample_interval =
+                Some((ts - last_ts).num_microseconds().unwrap_or_default() as f64 / 1_000_000.0);
+            record.cpu_percent = times.share(&last_times);
+            record.cpu_docker_percent = record
+                .cpu_percent
+                .map(|share| share * record.cpu_count as f64 * 100.0);
+        }
+        self.previous = times.map(|times| (ts, times));
+        if let Some(memory) = MemoryInfo::read() {
+            let usage = memory.total.saturating_sub(memory.available);
+            record.memory_usage = Some(usage);
+            record.memory_limit = Some(memory.total);
+            record.memory_percent =
+                (memory.total > 0).then(|| usage as f64 / memory.total as f64 * 100.0);
+        }
+        record.load_average = read_proc(PROC_LOADAVG)
+            .as_deref()
+            .and_then(parse_load_average);
+        #[cfg(feature = "enrichment-powercap")]
+        self.sample_energy(&mut record);
+        record
+    }
+
+    #[cfg(feature = "enrichment-powercap")]
+    fn sample_energy(&mut self, record: &mut Record) {
+        let duration = record.sample_interval.unwrap_or_default();
+        #[cfg(feature = "enrichment-sensors")]
+        let sensor = self.sample_sensor(duration);
+        #[cfg(feature = "enrichment-sensors")]
+        {
+            record.sensor_energy = sensor.ok().flatten();
+        }
+        let deltas = match self.energy {
+            Some(EnergySource::Rapl(ref powercap)) => {
+                let current = powercap::read_zones(powercap);
+                let deltas = match self.last_energy {
+                    Some((last_ts, ref last)) => {
+                        let elapsed = (record.ts - last_ts).to_std().unwrap_or_default();
+                        powercap::zone_deltas(last, &current, elapsed)
+                    }
+                    None => Default::default(),
+                };
+                self.last_energy = Some((record.ts, current));
+                record.cpu_energy = powercap::package_energy(&deltas.zones);
+                record.dram_energy = powercap::dram_energy(&deltas.zones);
+                deltas
+            }
+            #[cfg(feature = "enrichment-sensors")]
+            Some(EnergySource::Sensor) => {
+                record.cpu_energy = sensor.ok().flatten();
+                powercap::ZoneDeltas {
+                    zones: Vec::new(),
+                    anomaly: sensor.err(),
+                }
+            }
+            Some(EnergySource::Model(ref model)) => {
+                record.cpu_energy = record
+                    .cpu_percent
+                    .map(|utilization| model.energy(utilization, duration));
+                Default::default()
+            }
+            None => return,
+        };
+        record.energy_anomaly = deltas.anomaly;
+        record.energy_method = self.energy.as_ref().map(EnergySource::method);
+        self.cpu_energy_total += record.cpu_energy.unwrap_or_default();
+        record.cpu_energy_total = Some(self.cpu_energy_total);
+        record.power_watts = powercap::average_power(record.cpu_energy, duration);
+        record.static_energy = self.static_energy(record.cpu_energy, duration);
+        record.dynamic_energy = record
+            .cpu_energy
+            .zip(record.static_energy)
+            .map(|(energy, idle)| energy - idle);
+        let attributed = self.ledger.attribute(&powercap::HostEnergy {
+            package: record.cpu_energy,
+            dram: record.dram_energy,
+            anomaly: record.energy_anomaly,
+            duration,
+            // cpu time used on the host during the same window as the energy
+            busy_time: record.cpu_percent.unwrap_or_default() * duration * record.cpu_count as f64,
+            memory_total: record.memory_limit,
+            static_energy: record.static_energy,
+            policy: self
+                .static_power
+                .map(|(policy, _)| policy)
+                .unwrap_or_default(),
+        });
+        if record.cpu_energy.is_some() || record.dram_energy.is_some() {
+            let total =
+                record.cpu_energy.unwrap_or_default() + record.dram_energy.unwrap_or_default();
+            record.unattributed_energy = Some(total - attributed);
+        }
+        record.energy_zones = deltas.zones;
+    }
+
+    /// Part of the energy of the packages consumed even when the host is idle,
+    /// from the idle power of the power model, or from the lowest power seen so
+    /// far, when a policy splits it.
+    #[cfg(feature = "enrichment-powercap")]
+    fn static_energy(&mut self, package: Option<f64>, duration: f64) -> Option<f64> {
+        let (_, idle_power) = self.static_power?;
+        let power = powercap::average_power(package, duration)?;
+        let lowest = self.lowest_power.map_or(power, |lowest| lowest.min(power));
+        self.lowest_power = Some(lowest);
+        let idle = idle_power.unwrap_or(lowest);
+        package.map(|energy| match power > 0.0 {
+            true => (energy * idle / power).min(energy),
+            false => 0.0,
+        })
+    }
+
+    /// Energy measured by the power sensor since its previous reading.
+    #[cfg(feature = "enrichment-sensors")]
+    fn sample_sensor(&mut self, duration: f64) -> Result<Option<f64>, EnergyAnomaly> {
+        let reading = match self.sensor {
+            Some(ref sensor) => sensor.read(),
+            None => return Ok(None),
+        };
+        let energy = match reading {
+            Some(ref reading) => reading.energy(self.last_reading.as_ref(), duration),
+            None => Ok(None),
+        };
+        self.last_reading = reading;
+        energy
+    }
+
+    pub async fn run(mut self, tx: mpsc::Sender<Message>, mut stopping: watch::Receiver<bool>) {
+        let mut ticker = tokio::time::interval(self.interval);
+        loop {
+            tokio::select! {
+                _ = ticker.tick() => {}
+                _ = stopping.changed() => return,
+            }
+            let record = self.sample(Utc::now());
+            if !self.emit {
+                continue;
+            }
+            if tx.send(Message::Record(Box::new(record))).await.is_err() {
+                tracing::debug!("exporter stopped, stopping host sampler");
+                return;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_cpu_count, parse_load_average, CpuTimes, MemoryInfo};
+
+    const STAT: &str = "cpu  100 10 50 800 40 0 0 0 0 0
+cpu0 50 5 25 400 20 0 0 0 0 0
+cpu1 50 5 25 400 20 0 0 0 0 0
+intr 1234 0 0
+ctxt 5678
+";
+
+    #[test]
+    fn should_parse_cpu_times() {
+        let previous = CpuTimes::parse(STAT).unwrap();
+        assert_eq!(
+            previous,
+            CpuTimes {
+                busy: 160,
+                total: 1000
+            }
+        );
+        assert_eq!(parse_cpu_count(STAT), 2);
+        let current = CpuTimes {
+            busy: 210,
+            total: 1200,
+        };
+        assert_eq!(current.share(&previous), Some(0.25));
+        assert_eq!(previous.share(&previous), None);
+        assert_eq!(previous.share(&current), None);
+    }
+
+    #[test]
+    fn should_parse_memory_info() {
+        let input = "MemTotal:       16384 kB
+MemFree:         1024 kB
+MemAvailable:    4096 kB
+Buffers:          512 kB
+";
+        assert_eq!(
+            MemoryInfo::parse(input),
+            Some(MemoryInfo {
+                total: 16384 * 1024,
+                available: 4096 * 1024,
+            })
+        );
+        assert_eq!(MemoryInfo::parse("MemTotal: 16384 kB"), None);
+    }
+
+    #[test]
+    fn should_parse_load_average() {
+        assert_eq!(
+            parse_load_average("0.52 0.58 0.59 1/467 12345\n"),
+            Some([0.52, 0.58, 0.59])
+        );
+        assert_eq!(parse_load_average("0.52"), None);
+    }
+}
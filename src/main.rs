@@ -1,14 +1,27 @@
+#![cfg_attr(all(test, feature = "bench"), feature(test))]
+
 mod enrichment;
 mod error;
 mod exporter;
 mod format;
+mod host;
 mod model;
+mod process;
 mod watcher;
 
+#[cfg(feature = "enrichment-powercap")]
+use crate::format::EnergyUnit;
+use crate::format::{CpuUnit, MemoryUnit, Projection, Rename, TimeUnit, Units};
+use crate::model::Field;
 use crate::watcher::Orchestrator;
 use clap::Parser;
 use std::convert::TryFrom;
-use tokio::sync::mpsc;
+use std::time::Duration;
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::sync::{mpsc, watch};
+
+/// Time given to the watchers to send their summary when shutting down.
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(10);
 
 #[derive(Parser)]
 #[clap(about, version, author)]
@@ -22,10 +35,107 @@ struct Params {
     /// Name or ID of the container to monitor, separated by comma.
     #[clap(long)]
     pub containers: Option<String>,
+    /// Disable inspecting the containers to attach their image, labels and other details.
+    #[clap(long)]
+    pub disable_metadata: bool,
+    /// Disable following the health check state, restart count and OOM kills of the containers.
+    #[clap(long)]
+    pub disable_health: bool,
+    /// Disable the periodic records of the whole host.
+    #[clap(long)]
+    pub disable_host: bool,
+    /// Interval between two samples of the host, at which its energy is split
+    /// between the containers, in seconds.
+    #[clap(long, default_value = "1")]
+    pub host_interval: u64,
+    /// Sample the processes of each container, which requires sharing the pid
+    /// namespace of the host when running in docker.
+    #[clap(long)]
+    pub processes: bool,
     /// Disable monitoring power consumption.
     #[cfg(feature = "enrichment-powercap")]
     #[clap(long)]
     pub disable_powercap: bool,
+    /// Power of the host when idle, in watts, to estimate the energy when RAPL isn't available.
+    #[cfg(feature = "enrichment-powercap")]
+    #[clap(long, requires = "max-watts", conflicts_with = "power-curve-file")]
+    pub idle_watts: Option<f64>,
+    /// Power of the host at full utilization, in watts, to estimate the energy when RAPL isn't
+    /// available.
+    #[cfg(feature = "enrichment-powercap")]
+    #[clap(long, requires = "idle-watts")]
+    pub max_watts: Option<f64>,
+    /// CSV file of the power of the host, in watts, according to the utilization of its cpu, in
+    /// percent, like `50,120.5` on each line, to estimate the energy when RAPL isn't available.
+    /// The utilizations go from 0 to 100, in increasing order.
+    #[cfg(feature = "enrichment-powercap")]
+    #[clap(long)]
+    pub power_curve_file: Option<std::path::PathBuf>,
+    /// Sensor of the host power: hwmon (the first chip with a power, leaving aside the graphic
+    /// cards), hwmon:<chip> (the chips with that name), power-meter (ACPI) or power-supply
+    /// (battery discharge). It measures the energy when RAPL isn't available, and is reported next
+    /// to RAPL otherwise.
+    #[cfg(feature = "enrichment-sensors")]
+    #[clap(long)]
+    pub power_sensor: Option<enrichment::sensors::PowerSensor>,
+    /// Policy splitting the static power of the host, consumed even when idle, between the
+    /// containers: none, equal, memory (by memory reservation, or usage without reservation) or
+    /// cpu (by cpu time). The static power is the idle power of the power model, or the lowest
+    /// power of the host seen so far.
+    #[cfg(feature = "enrichment-powercap")]
+    #[clap(long)]
+    pub static_power_policy: Option<enrichment::StaticPolicy>,
+    /// Power of the memory used by a container, in watts per gigabyte, meant for the hosts
+    /// without a RAPL domain for the memory.
+    #[cfg(feature = "enrichment-powercap")]
+    #[clap(long)]
+    pub memory_watts_per_gb: Option<f64>,
+    /// Energy of the block I/O of a container, in joules per gigabyte read or written.
+    #[cfg(feature = "enrichment-powercap")]
+    #[clap(long)]
+    pub io_joules_per_gb: Option<f64>,
+    /// Energy of the network traffic of a container, in joules per gigabyte received or sent.
+    #[cfg(feature = "enrichment-powercap")]
+    #[clap(long)]
+    pub network_joules_per_gb: Option<f64>,
+    /// Carbon intensity of the electricity, in grams of CO2 equivalent per kWh.
+    #[cfg(feature = "enrichment-carbon")]
+    #[clap(long, conflicts_with = "carbon-intensity-file")]
+    pub carbon_intensity: Option<f64>,
+    /// CSV or JSON file of the carbon intensity over time, in grams of CO2
+    /// equivalent per kWh, like `2022-01-01T00:00:00Z,320` on each line.
+    #[cfg(feature = "enrichment-carbon")]
+    #[clap(long)]
+    pub carbon_intensity_file: Option<std::path::PathBuf>,
+    /// Power usage effectiveness of the data center, multiplying the emissions.
+    #[cfg(feature = "enrichment-carbon")]
+    #[clap(long, default_value = "1.0")]
+    pub pue: f64,
+    /// JSON file of the tariff of the electricity, with its currency, its rate per kWh and
+    /// the time-of-use windows having another rate.
+    #[cfg(feature = "enrichment-cost")]
+    #[clap(long)]
+    pub tariff_file: Option<std::path::PathBuf>,
+    /// Fields to output, in order, separated by comma. All the fields are exported by default.
+    #[clap(long, use_value_delimiter = true)]
+    pub fields: Vec<Field>,
+    /// Renaming of the output fields, like `cpuEnergy=energy_joules`, separated by comma.
+    /// Not available with the csv format, which has no header.
+    #[clap(long, use_value_delimiter = true)]
+    pub rename: Vec<Rename>,
+    /// Unit of the memory fields: bytes, kib or mib.
+    #[clap(long, default_value = "bytes")]
+    pub memory_unit: MemoryUnit,
+    /// Unit of the energy fields, including the totals: uj, j, wh or kwh.
+    #[cfg(feature = "enrichment-powercap")]
+    #[clap(long, default_value = "uj")]
+    pub energy_unit: EnergyUnit,
+    /// Unit of the cpu fields: ratio (from 0 to 1), percent (from 0 to 100) or core-percent (percent of one core).
+    #[clap(long, default_value = "ratio")]
+    pub cpu_unit: CpuUnit,
+    /// Unit of the timestamps: s, ms, ns or rfc3339.
+    #[clap(long, default_value = "s")]
+    pub time_unit: TimeUnit,
     #[clap(subcommand)]
     pub output: exporter::Output,
 }
@@ -39,12 +149,60 @@ async fn main() {
         .init();
 
     let (tx, mut rx) = mpsc::channel(params.buffer_size);
-    let mut exporter = params.output.exporter();
+    let projection = Projection::new(params.fields.clone(), params.rename.clone())
+        .expect("invalid fields")
+        .with_units(Units {
+            memory: params.memory_unit,
+            #[cfg(feature = "enrichment-powercap")]
+            energy: params.energy_unit,
+            cpu: params.cpu_unit,
+            time: params.time_unit,
+        });
+    let mut exporter = params.output.exporter(projection);
+    let (stop, stopping) = watch::channel(false);
     tokio::spawn(async move {
         let mut orchestrator = Orchestrator::try_from(params).expect("couldn't build orchestrator");
-        orchestrator.run(tx).await
+        orchestrator.run(tx, stopping).await
     });
-    while let Some(snap) = rx.recv().await {
-        exporter.handle(snap).expect("couldn't export event");
+    let shutdown = shutdown_signal();
+    tokio::pin!(shutdown);
+    loop {
+        tokio::select! {
+            message = rx.recv() => match message {
+                Some(message) => exporter
+                    .handle_message(message)
+                    .expect("couldn't export event"),
+                None => break,
+            },
+            _ = &mut shutdown => {
+                tracing::info!("shutting down");
+                break;
+            }
+        }
+    }
+    // the watchers send the summary of their container before stopping
+    if stop.send(true).is_ok() {
+        let remaining = async {
+            while let Some(message) = rx.recv().await {
+                exporter
+                    .handle_message(message)
+                    .expect("couldn't export event");
+            }
+        };
+        if tokio::time::timeout(SHUTDOWN_TIMEOUT, remaining)
+            .await
+            .is_err()
+        {
+            tracing::warn!("couldn't export every summary before shutting down");
+        }
+    }
+    exporter.close().expect("couldn't close exporter");
+}
+
+async fn shutdown_signal() {
+    let mut terminate = signal(SignalKind::terminate()).expect("couldn't listen to signals");
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => {},
+        _ = terminate.recv() => {},
     }
 }
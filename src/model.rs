@@ -1,77 +1,2066 @@
-use bollard::container::Stats;
+use bollard::container::{CPUStats, MemoryStatsStats, Stats};
+use chrono::{DateTime, Duration, Utc};
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap};
+use std::str::FromStr;
+use std::sync::Arc;
+
+/// The energy is kept in micro joules, as read from RAPL.
+#[cfg(feature = "enrichment-powercap")]
+pub const MICROJOULES_PER_JOULE: f64 = 1_000_000.0;
+#[cfg(any(feature = "enrichment-carbon", feature = "enrichment-cost"))]
+pub const MICROJOULES_PER_KILOWATT_HOUR: f64 = MICROJOULES_PER_JOULE * 3.6e6;
+
+/// Message sent by the container watchers to the exporter.
+#[derive(Debug)]
+pub enum Message {
+    /// A container started being watched.
+    Started(String),
+    /// A new record has been collected.
+    Record(Box<Record>),
+    /// A container is not watched anymore.
+    Stopped(String),
+}
 
 #[derive(Debug)]
 #[cfg_attr(test, derive(Clone))]
-#[cfg_attr(
-    feature = "formatter-json",
-    derive(serde::Serialize),
-    serde(rename_all = "camelCase")
-)]
 pub struct Record {
+    pub record_type: RecordType,
+    /// Lifecycle event, for the event records.
+    pub event: Option<ContainerEvent>,
     pub container_id: String,
     pub container_name: String,
-    pub ts: i64,
+    /// Process of the container, for the process records.
+    pub process_id: Option<u64>,
+    pub process_command: Option<String>,
+    /// Details of the container, shared by all its records.
+    pub metadata: Option<Arc<ContainerMetadata>>,
+    pub health: Option<Arc<ContainerHealth>>,
+    pub ts: DateTime<Utc>,
     pub pid_count: Option<u64>,
     pub pid_limit: Option<u64>,
     pub memory_usage: Option<u64>,
     pub memory_limit: Option<u64>,
-    pub cpu_percent: f64,
+    pub memory_cache: Option<u64>,
+    pub memory_rss: Option<u64>,
+    pub memory_inactive_file: Option<u64>,
+    /// Usage without the inactive page cache, as reported by `docker stats`.
+    pub memory_working_set: Option<u64>,
+    /// Memory swapped out, missing when the key set of the cgroup doesn't give it.
+    pub memory_swap: Option<u64>,
+    /// Working set relative to the limit, between 0 and 100.
+    pub memory_percent: Option<f64>,
+    /// Time elapsed since the previous sample, in seconds, missing for the first one.
+    pub sample_interval: Option<f64>,
+    /// Share of the whole host, between 0 and 1, missing for the first sample.
+    pub cpu_percent: Option<f64>,
+    /// Percentage as displayed by `docker stats`, between 0 and 100 times the
+    /// number of cores.
+    pub cpu_docker_percent: Option<f64>,
     pub cpu_count: u64,
+    /// Cpu time used since the previous sample, in nanoseconds.
+    pub cpu_time: Option<u64>,
+    /// Time spent in user and kernel mode since the previous sample, in nanoseconds.
+    pub cpu_user_time: Option<u64>,
+    pub cpu_kernel_time: Option<u64>,
+    /// Enforcement periods, throttled periods and throttled time, in nanoseconds,
+    /// since the previous sample.
+    pub cpu_throttling_periods: Option<u64>,
+    pub cpu_throttled_periods: Option<u64>,
+    pub cpu_throttled_time: Option<u64>,
+    /// Time spent on each core since the previous sample, in nanoseconds, only
+    /// provided by docker on cgroup v1.
+    pub cpu_per_core: Option<Vec<u64>>,
+    /// Load averages over 1, 5 and 15 minutes, for the host records.
+    pub load_average: Option<[f64; 3]>,
+    /// Energy of the processor packages, attributed by cpu time over the ticks of
+    /// the host sampler since the previous sample, in micro joules.
     #[cfg(feature = "enrichment-powercap")]
     pub cpu_energy: Option<f64>,
+    /// Energy of the memory, attributed by share of the memory of the host, in
+    /// micro joules.
+    #[cfg(feature = "enrichment-powercap")]
+    pub dram_energy: Option<f64>,
+    /// Energy of the memory of the container estimated with a coefficient per
+    /// gigabyte, in micro joules.
+    #[cfg(feature = "enrichment-powercap")]
+    pub memory_energy: Option<f64>,
+    /// Energy of the block I/O of the container estimated with a coefficient per
+    /// gigabyte, in micro joules.
+    #[cfg(feature = "enrichment-powercap")]
+    pub io_energy: Option<f64>,
+    /// Energy of the network traffic of the container estimated with a
+    /// coefficient per gigabyte, in micro joules.
+    #[cfg(feature = "enrichment-powercap")]
+    pub network_energy: Option<f64>,
+    /// Energy of the processor packages since the container started being
+    /// watched, in micro joules. Like the other energy fields, it's exported in
+    /// the unit given by `--energy-unit`, which defaults to micro joules, so
+    /// `--energy-unit j` gives it in joules.
+    #[cfg(feature = "enrichment-powercap")]
+    pub cpu_energy_total: Option<f64>,
+    /// Part of `cpu_energy` consumed by the activity of the container, when the
+    /// static power of the host is split with a policy, in micro joules.
+    #[cfg(feature = "enrichment-powercap")]
+    pub dynamic_energy: Option<f64>,
+    /// Part of `cpu_energy` consumed by the host even when idle, attributed to
+    /// the container by the policy, in micro joules.
+    #[cfg(feature = "enrichment-powercap")]
+    pub static_energy: Option<f64>,
+    /// Average power of the processor packages over the window of `cpu_energy`,
+    /// in watts.
+    #[cfg(feature = "enrichment-powercap")]
+    pub power_watts: Option<f64>,
+    /// Energy of the host that wasn't attributed to any watched container, for
+    /// the host records.
+    #[cfg(feature = "enrichment-powercap")]
+    pub unattributed_energy: Option<f64>,
+    /// Energy of each RAPL zone, for the host records.
+    #[cfg(feature = "enrichment-powercap")]
+    pub energy_zones: Vec<EnergyZone>,
+    /// Set when a RAPL counter wrapped or couldn't be trusted during the sample.
+    #[cfg(feature = "enrichment-powercap")]
+    pub energy_anomaly: Option<EnergyAnomaly>,
+    /// Whether the energy is measured or estimated from a power model.
+    #[cfg(feature = "enrichment-powercap")]
+    pub energy_method: Option<EnergyMethod>,
+    /// Energy measured by the power sensor, for the host records, in micro joules.
+    #[cfg(feature = "enrichment-sensors")]
+    pub sensor_energy: Option<f64>,
+    /// Emissions of the energy attributed to the container since the previous
+    /// sample, in grams of CO2 equivalent.
+    #[cfg(feature = "enrichment-carbon")]
+    pub co2_grams: Option<f64>,
+    /// Emissions since the container started being watched, in grams of CO2
+    /// equivalent.
+    #[cfg(feature = "enrichment-carbon")]
+    pub co2_grams_total: Option<f64>,
+    /// Cost of the energy attributed to the container since the previous sample.
+    #[cfg(feature = "enrichment-cost")]
+    pub cost: Option<f64>,
+    /// Cost since the container started being watched.
+    #[cfg(feature = "enrichment-cost")]
+    pub cost_total: Option<f64>,
+    /// Currency of the costs, from the tariff.
+    #[cfg(feature = "enrichment-cost")]
+    pub cost_currency: Option<String>,
+    /// Counters summed over all the interfaces, missing without network.
+    pub network: Option<NetworkUsage>,
+    pub network_rates: Option<NetworkRates>,
+    /// Counters of each interface, ordered by name.
+    pub networks: Vec<NetworkInterface>,
+    /// Block I/O summed over all the devices, missing without block I/O stats.
+    pub block_io: Option<BlockUsage>,
+    pub block_io_rates: Option<BlockRates>,
+    /// Block I/O of each device, ordered by major and minor numbers.
+    pub block_devices: Vec<BlockDevice>,
+}
+
+/// Type of a record, to tell the metric samples apart from the lifecycle events.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecordType {
+    Sample,
+    Event,
+    /// Usage of the whole host, without container.
+    Host,
+    /// Share of the usage of a container taken by one of its processes.
+    Process,
+    /// Totals of a container, sent when it stops being watched.
+    Summary,
+}
+
+impl RecordType {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Sample => "sample",
+            Self::Event => "event",
+            Self::Host => "host",
+            Self::Process => "process",
+            Self::Summary => "summary",
+        }
+    }
+}
+
+/// Lifecycle event of a container, as received from docker.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ContainerEvent {
+    /// Action of the event, like `die` or `oom`.
+    pub action: String,
+    /// Exit code of the main process, for the `die` events.
+    pub exit_code: Option<i64>,
+    /// Signal sent to the container, for the `kill` events.
+    pub signal: Option<String>,
+}
+
+impl ContainerEvent {
+    /// Actions that are exported as event records.
+    pub const ACTIONS: &'static [&'static str] = &[
+        "start", "stop", "die", "kill", "oom", "pause", "unpause", "restart", "destroy",
+    ];
+}
+
+/// Details of a container that don't change while it's running, apart from its name.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ContainerMetadata {
+    /// Name of the image, as given when creating the container.
+    pub image: Option<String>,
+    pub image_id: Option<String>,
+    /// Labels, ordered by key.
+    pub labels: BTreeMap<String, String>,
+    pub hostname: Option<String>,
+    pub created_at: Option<DateTime<Utc>>,
+    pub started_at: Option<DateTime<Utc>>,
+    pub restart_policy: Option<String>,
+}
+
+impl ContainerMetadata {
+    pub const COMPOSE_PROJECT_LABEL: &'static str = "com.docker.compose.project";
+    pub const COMPOSE_SERVICE_LABEL: &'static str = "com.docker.compose.service";
+
+    pub fn compose_project(&self) -> Option<&str> {
+        self.labels
+            .get(Self::COMPOSE_PROJECT_LABEL)
+            .map(String::as_str)
+    }
+
+    pub fn compose_service(&self) -> Option<&str> {
+        self.labels
+            .get(Self::COMPOSE_SERVICE_LABEL)
+            .map(String::as_str)
+    }
+}
+
+/// Health of a container, that changes with its health checks and restarts.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ContainerHealth {
+    /// Status of the health check, `starting`, `healthy` or `unhealthy`, missing
+    /// without health check.
+    pub status: Option<String>,
+    /// Number of consecutive failed health checks.
+    pub failing_streak: Option<u64>,
+    pub restart_count: Option<u64>,
+    /// Whether the container was killed for running out of memory.
+    pub oom_killed: Option<bool>,
+}
+
+/// Cumulative network counters, of an interface or of all of them.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct NetworkUsage {
+    pub rx_bytes: u64,
+    pub rx_packets: u64,
+    pub rx_errors: u64,
+    pub rx_dropped: u64,
+    pub tx_bytes: u64,
+    pub tx_packets: u64,
+    pub tx_errors: u64,
+    pub tx_dropped: u64,
+}
+
+impl From<&bollard::container::NetworkStats> for NetworkUsage {
+    fn from(item: &bollard::container::NetworkStats) -> Self {
+        Self {
+            rx_bytes: item.rx_bytes,
+            rx_packets: item.rx_packets,
+            rx_errors: item.rx_errors,
+            rx_dropped: item.rx_dropped,
+            tx_bytes: item.tx_bytes,
+            tx_packets: item.tx_packets,
+            tx_errors: item.tx_errors,
+            tx_dropped: item.tx_dropped,
+        }
+    }
+}
+
+impl std::ops::Add for NetworkUsage {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        Self {
+            rx_bytes: self.rx_bytes + other.rx_bytes,
+            rx_packets: self.rx_packets + other.rx_packets,
+            rx_errors: self.rx_errors + other.rx_errors,
+            rx_dropped: self.rx_dropped + other.rx_dropped,
+            tx_bytes: self.tx_bytes + other.tx_bytes,
+            tx_packets: self.tx_packets + other.tx_packets,
+            tx_errors: self.tx_errors + other.tx_errors,
+            tx_dropped: self.tx_dropped + other.tx_dropped,
+        }
+    }
+}
+
+impl NetworkUsage {
+    /// Per-second rates since the previous counters, missing when a counter went
+    /// backwards, which happens when the interface is recreated.
+    pub fn rates(&self, previous: &Self, seconds: f64) -> Option<NetworkRates> {
+        let rate = |current: u64, previous: u64| {
+            current
+                .checked_sub(previous)
+                .map(|delta| delta as f64 / seconds)
+        };
+        Some(NetworkRates {
+            rx_bytes: rate(self.rx_bytes, previous.rx_bytes)?,
+            rx_packets: rate(self.rx_packets, previous.rx_packets)?,
+            tx_bytes: rate(self.tx_bytes, previous.tx_bytes)?,
+            tx_packets: rate(self.tx_packets, previous.tx_packets)?,
+        })
+    }
+}
+
+/// Per-second rates of the network counters.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct NetworkRates {
+    pub rx_bytes: f64,
+    pub rx_packets: f64,
+    pub tx_bytes: f64,
+    pub tx_packets: f64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct NetworkInterface {
+    pub name: String,
+    pub usage: NetworkUsage,
+    pub rates: Option<NetworkRates>,
+}
+
+impl NetworkInterface {
+    fn value(&self) -> Value<'_> {
+        let rates = self.rates.as_ref();
+        Value::Map(vec![
+            ("rxBytes".into(), Value::Unsigned(self.usage.rx_bytes)),
+            ("rxPackets".into(), Value::Unsigned(self.usage.rx_packets)),
+            ("rxErrors".into(), Value::Unsigned(self.usage.rx_errors)),
+            ("rxDropped".into(), Value::Unsigned(self.usage.rx_dropped)),
+            ("txBytes".into(), Value::Unsigned(self.usage.tx_bytes)),
+            ("txPackets".into(), Value::Unsigned(self.usage.tx_packets)),
+            ("txErrors".into(), Value::Unsigned(self.usage.tx_errors)),
+            ("txDropped".into(), Value::Unsigned(self.usage.tx_dropped)),
+            (
+                "rxBytesRate".into(),
+                Value::from(rates.map(|rates| rates.rx_bytes)),
+            ),
+            (
+                "txBytesRate".into(),
+                Value::from(rates.map(|rates| rates.tx_bytes)),
+            ),
+        ])
+    }
+}
+
+/// Type of the value of a network interface, matching [`NetworkInterface::value`].
+#[cfg(feature = "formatter-avro")]
+const NETWORK_INTERFACE: Kind = Kind::Record(
+    "NetworkInterface",
+    &[
+        ("rxBytes", Kind::Integer),
+        ("rxPackets", Kind::Integer),
+        ("rxErrors", Kind::Integer),
+        ("rxDropped", Kind::Integer),
+        ("txBytes", Kind::Integer),
+        ("txPackets", Kind::Integer),
+        ("txErrors", Kind::Integer),
+        ("txDropped", Kind::Integer),
+        ("rxBytesRate", Kind::Nullable(&Kind::Float)),
+        ("txBytesRate", Kind::Nullable(&Kind::Float)),
+    ],
+);
+
+/// Cumulative block I/O of a device, or of all of them.
+///
+/// The number of operations is only provided by docker on cgroup v1.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BlockUsage {
+    pub read_bytes: u64,
+    pub write_bytes: u64,
+    pub read_ops: Option<u64>,
+    pub write_ops: Option<u64>,
+}
+
+impl std::ops::Add for BlockUsage {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        let sum = |first: Option<u64>, second: Option<u64>| match (first, second) {
+            (None, None) => None,
+            (first, second) => Some(first.unwrap_or_default() + second.unwrap_or_default()),
+        };
+        Self {
+            read_bytes: self.read_bytes + other.read_bytes,
+            write_bytes: self.write_bytes + other.write_bytes,
+            read_ops: sum(self.read_ops, other.read_ops),
+            write_ops: sum(self.write_ops, other.write_ops),
+        }
+    }
+}
+
+impl BlockUsage {
+    /// Per-second rates since the previous counters, missing when a counter went
+    /// backwards.
+    pub fn rates(&self, previous: &Self, seconds: f64) -> Option<BlockRates> {
+        let rate = |current: u64, previous: u64| {
+            current
+                .checked_sub(previous)
+                .map(|delta| delta as f64 / seconds)
+        };
+        let optional_rate = |current: Option<u64>, previous: Option<u64>| match (current, previous)
+        {
+            (Some(current), Some(previous)) => rate(current, previous).map(Some),
+            _ => Some(None),
+        };
+        Some(BlockRates {
+            read_bytes: rate(self.read_bytes, previous.read_bytes)?,
+            write_bytes: rate(self.write_bytes, previous.write_bytes)?,
+            read_ops: optional_rate(self.read_ops, previous.read_ops)?,
+            write_ops: optional_rate(self.write_ops, previous.write_ops)?,
+        })
+    }
+}
+
+/// Per-second rates of the block I/O counters.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct BlockRates {
+    pub read_bytes: f64,
+    pub write_bytes: f64,
+    pub read_ops: Option<f64>,
+    pub write_ops: Option<f64>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BlockDevice {
+    /// Device numbers, written `major:minor`.
+    pub name: String,
+    pub usage: BlockUsage,
+    pub rates: Option<BlockRates>,
+}
+
+impl BlockDevice {
+    /// Sums the read and write entries of each device, the operation names being
+    /// capitalized on cgroup v1 and lower case on cgroup v2.
+    fn collect(stats: &bollard::container::BlkioStats) -> Vec<Self> {
+        let mut devices: BTreeMap<(u64, u64), BlockUsage> = BTreeMap::new();
+        for entry in stats.io_service_bytes_recursive.iter().flatten() {
+            let usage = devices.entry((entry.major, entry.minor)).or_default();
+            match entry.op.to_lowercase().as_str() {
+                "read" => usage.read_bytes += entry.value,
+                "write" => usage.write_bytes += entry.value,
+                _ => {}
+            }
+        }
+        for entry in stats.io_serviced_recursive.iter().flatten() {
+            let usage = devices.entry((entry.major, entry.minor)).or_default();
+            match entry.op.to_lowercase().as_str() {
+                "read" => *usage.read_ops.get_or_insert(0) += entry.value,
+                "write" => *usage.write_ops.get_or_insert(0) += entry.value,
+                _ => {}
+            }
+        }
+        devices
+            .into_iter()
+            .map(|((major, minor), usage)| Self {
+                name: format!("{}:{}", major, minor),
+                usage,
+                rates: None,
+            })
+            .collect()
+    }
+
+    fn value(&self) -> Value<'_> {
+        let rates = self.rates.as_ref();
+        Value::Map(vec![
+            ("readBytes".into(), Value::Unsigned(self.usage.read_bytes)),
+            ("writeBytes".into(), Value::Unsigned(self.usage.write_bytes)),
+            ("readOps".into(), Value::from(self.usage.read_ops)),
+            ("writeOps".into(), Value::from(self.usage.write_ops)),
+            (
+                "readBytesRate".into(),
+                Value::from(rates.map(|rates| rates.read_bytes)),
+            ),
+            (
+                "writeBytesRate".into(),
+                Value::from(rates.map(|rates| rates.write_bytes)),
+            ),
+        ])
+    }
+}
+
+/// Type of the value of a block device, matching [`BlockDevice::value`].
+#[cfg(feature = "formatter-avro")]
+const BLOCK_DEVICE: Kind = Kind::Record(
+    "BlockDevice",
+    &[
+        ("readBytes", Kind::Integer),
+        ("writeBytes", Kind::Integer),
+        ("readOps", Kind::Nullable(&Kind::Integer)),
+        ("writeOps", Kind::Nullable(&Kind::Integer)),
+        ("readBytesRate", Kind::Nullable(&Kind::Float)),
+        ("writeBytesRate", Kind::Nullable(&Kind::Float)),
+    ],
+);
+
+/// Cumulative counters of a record, kept by the watchers to compute the rates
+/// of the next record.
+#[derive(Debug, Clone)]
+pub struct Counters {
+    ts: DateTime<Utc>,
+    network: Option<NetworkUsage>,
+    networks: HashMap<String, NetworkUsage>,
+    block_io: Option<BlockUsage>,
+    block_devices: HashMap<String, BlockUsage>,
+}
+
+/// Page cache, resident set, inactive page cache and swap, from the cgroup v1 or v2 key sets.
+///
+/// The swap, `total_swap` in v1 and `swap` in v2, isn't part of the key sets of
+/// bollard 0.12, so it stays missing until bollard gives it.
+fn memory_details(
+    stats: Option<&MemoryStatsStats>,
+) -> (Option<u64>, Option<u64>, Option<u64>, Option<u64>) {
+    match stats {
+        Some(MemoryStatsStats::V1(stats)) => (
+            Some(stats.total_cache),
+            Some(stats.total_rss),
+            Some(stats.total_inactive_file),
+            None,
+        ),
+        Some(MemoryStatsStats::V2(stats)) => (
+            Some(stats.file),
+            Some(stats.anon),
+            Some(stats.inactive_file),
+            None,
+        ),
+        None => (None, None, None, None),
+    }
+}
+
+/// Difference between the cpu counters of a sample and the ones of the previous
+/// sample, that docker sets to zero for the first sample.
+struct CpuDelta<'a> {
+    current: &'a CPUStats,
+    previous: &'a CPUStats,
+}
+
+impl<'a> CpuDelta<'a> {
+    fn new(current: &'a CPUStats, previous: &'a CPUStats) -> Self {
+        Self { current, previous }
+    }
+
+    fn is_first(&self) -> bool {
+        self.previous.system_cpu_usage.unwrap_or_default() == 0
+            && self.previous.cpu_usage.total_usage == 0
+    }
+
+    /// Share of the whole host used by the container, relative to the cpu time of
+    /// the host when available, or to the sample interval otherwise.
+    fn host_share(&self, interval: Option<Duration>) -> Option<f64> {
+        let usage = self.delta(|stats| stats.cpu_usage.total_usage)?;
+        let system = self
+            .delta(|stats| stats.system_cpu_usage.unwrap_or_default())
+            .filter(|system| *system > 0);
+        if let Some(system) = system {
+            return Some(usage as f64 / system as f64);
+        }
+        let available = interval?.num_nanoseconds()? as f64 * self.cores() as f64;
+        if available > 0.0 {
+            Some(usage as f64 / available)
+        } else {
+            None
+        }
+    }
+
+    fn cores(&self) -> u64 {
+        self.current
+            .online_cpus
+            .filter(|count| *count > 0)
+            .unwrap_or(1)
+    }
+
+    fn delta<F: Fn(&CPUStats) -> u64>(&self, counter: F) -> Option<u64> {
+        if self.is_first() {
+            return None;
+        }
+        counter(self.current).checked_sub(counter(self.previous))
+    }
+
+    fn per_core(&self) -> Option<Vec<u64>> {
+        if self.is_first() {
+            return None;
+        }
+        let current = self.current.cpu_usage.percpu_usage.as_ref()?;
+        let previous = self.previous.cpu_usage.percpu_usage.as_ref()?;
+        if current.len() != previous.len() {
+            return None;
+        }
+        current
+            .iter()
+            .zip(previous.iter())
+            .map(|(current, previous)| current.checked_sub(*previous))
+            .collect()
+    }
+}
+
+/// Memory usage without the inactive page cache, that can be reclaimed, computed
+/// the same way as the docker cli.
+fn working_set(usage: Option<u64>, inactive_file: Option<u64>) -> Option<u64> {
+    usage.map(|usage| match inactive_file {
+        Some(inactive) if inactive < usage => usage - inactive,
+        _ => usage,
+    })
 }
 
 impl From<Stats> for Record {
     fn from(item: Stats) -> Self {
-        let cpu_delta =
-            item.cpu_stats.cpu_usage.total_usage - item.precpu_stats.cpu_usage.total_usage;
-        let system_delta = item.cpu_stats.system_cpu_usage.unwrap_or_default()
-            - item.precpu_stats.system_cpu_usage.unwrap_or_default();
-        let cpu_count = item.cpu_stats.online_cpus.unwrap_or(1);
-        let cpu_percent = cpu_delta as f64 / system_delta as f64;
+        // docker sets the previous read time to year one for the first sample
+        let interval = (item.preread.timestamp() > 0)
+            .then(|| item.read - item.preread)
+            .filter(|interval| *interval > Duration::zero());
+        let cpu = CpuDelta::new(&item.cpu_stats, &item.precpu_stats);
+        let cpu_count = cpu.cores();
+        let cpu_percent = cpu.host_share(interval);
+        let cpu_docker_percent = cpu_percent.map(|share| share * cpu_count as f64 * 100.0);
+        let cpu_time = cpu.delta(|stats| stats.cpu_usage.total_usage);
+        let cpu_user_time = cpu.delta(|stats| stats.cpu_usage.usage_in_usermode);
+        let cpu_kernel_time = cpu.delta(|stats| stats.cpu_usage.usage_in_kernelmode);
+        let cpu_throttling_periods = cpu.delta(|stats| stats.throttling_data.periods);
+        let cpu_throttled_periods = cpu.delta(|stats| stats.throttling_data.throttled_periods);
+        let cpu_throttled_time = cpu.delta(|stats| stats.throttling_data.throttled_time);
+        let cpu_per_core = cpu.per_core();
+        let mut networks: Vec<NetworkInterface> = item
+            .networks
+            .iter()
+            .flatten()
+            .map(|(name, stats)| NetworkInterface {
+                name: name.clone(),
+                usage: NetworkUsage::from(stats),
+                rates: None,
+            })
+            .collect();
+        networks.sort_by(|first, second| first.name.cmp(&second.name));
+        let network = item.networks.as_ref().map(|_| {
+            networks
+                .iter()
+                .fold(NetworkUsage::default(), |total, iface| total + iface.usage)
+        });
+        let (memory_cache, memory_rss, memory_inactive_file, memory_swap) =
+            memory_details(item.memory_stats.stats.as_ref());
+        let memory_working_set = working_set(item.memory_stats.usage, memory_inactive_file);
+        let memory_percent = match (memory_working_set, item.memory_stats.limit) {
+            (Some(working_set), Some(limit)) if limit > 0 => {
+                Some(working_set as f64 * 100.0 / limit as f64)
+            }
+            _ => None,
+        };
+        let block_devices = BlockDevice::collect(&item.blkio_stats);
+        let block_io = item
+            .blkio_stats
+            .io_service_bytes_recursive
+            .as_ref()
+            .map(|_| {
+                block_devices
+                    .iter()
+                    .fold(BlockUsage::default(), |total, device| total + device.usage)
+            });
 
         Self {
-            container_id: item.id,
-            container_name: item.name.trim_start_matches('/').to_string(),
-            ts: item.read.timestamp(),
             pid_count: item.pids_stats.current,
             pid_limit: item.pids_stats.limit,
             memory_usage: item.memory_stats.usage,
             memory_limit: item.memory_stats.limit,
+            memory_cache,
+            memory_rss,
+            memory_inactive_file,
+            memory_working_set,
+            memory_swap,
+            memory_percent,
+            sample_interval: interval
+                .and_then(|interval| interval.num_microseconds())
+                .map(|micros| micros as f64 / 1_000_000.0),
             cpu_percent,
+            cpu_docker_percent,
             cpu_count,
-            #[cfg(feature = "enrichment-powercap")]
-            cpu_energy: None,
+            cpu_time,
+            cpu_user_time,
+            cpu_kernel_time,
+            cpu_throttling_periods,
+            cpu_throttled_periods,
+            cpu_throttled_time,
+            cpu_per_core,
+            network,
+            networks,
+            block_io,
+            block_devices,
+            ..Self::new(
+                item.id,
+                item.name.trim_start_matches('/').to_string(),
+                item.read,
+            )
         }
     }
 }
 
+/// Energy consumed by a RAPL zone, in micro joules.
 #[cfg(feature = "enrichment-powercap")]
+#[derive(Debug, Clone, PartialEq)]
+pub struct EnergyZone {
+    /// Name of the zone, like `package-0` or `package-0/dram`.
+    pub name: String,
+    pub energy: f64,
+}
+
+/// Anomaly of the RAPL counters during a sample.
+#[cfg(feature = "enrichment-powercap")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum EnergyAnomaly {
+    /// A counter wrapped around and its energy has been corrected.
+    Wrapped,
+    /// A counter was reset or jumped further than its range, so the energy of
+    /// the sample is missing.
+    Discarded,
+}
+
+#[cfg(feature = "enrichment-powercap")]
+impl EnergyAnomaly {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Wrapped => "wrapped",
+            Self::Discarded => "discarded",
+        }
+    }
+}
+
+/// How the energy of a record has been obtained.
+#[cfg(feature = "enrichment-powercap")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EnergyMethod {
+    /// Read from the RAPL counters.
+    Measured,
+    /// Computed from the utilization of the cpu with a power model.
+    Estimated,
+}
+
+#[cfg(feature = "enrichment-powercap")]
+impl EnergyMethod {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Measured => "measured",
+            Self::Estimated => "estimated",
+        }
+    }
+}
+
 impl Record {
-    pub fn with_energy(mut self, total_cpu_energy: Option<u64>) -> Self {
-        if let Some(total_cpu_energy) = total_cpu_energy {
-            self.cpu_energy = Some(self.cpu_percent * total_cpu_energy as f64);
+    /// Empty metric sample, to be completed.
+    pub fn new(container_id: String, container_name: String, ts: DateTime<Utc>) -> Self {
+        Self {
+            record_type: RecordType::Sample,
+            event: None,
+            container_id,
+            container_name,
+            process_id: None,
+            process_command: None,
+            metadata: None,
+            health: None,
+            ts,
+            pid_count: None,
+            pid_limit: None,
+            memory_usage: None,
+            memory_limit: None,
+            memory_cache: None,
+            memory_rss: None,
+            memory_inactive_file: None,
+            memory_working_set: None,
+            memory_swap: None,
+            memory_percent: None,
+            sample_interval: None,
+            cpu_percent: None,
+            cpu_docker_percent: None,
+            cpu_count: 0,
+            cpu_time: None,
+            cpu_user_time: None,
+            cpu_kernel_time: None,
+            cpu_throttling_periods: None,
+            cpu_throttled_periods: None,
+            cpu_throttled_time: None,
+            cpu_per_core: None,
+            load_average: None,
+            #[cfg(feature = "enrichment-powercap")]
+            cpu_energy: None,
+            #[cfg(feature = "enrichment-powercap")]
+            dram_energy: None,
+            #[cfg(feature = "enrichment-powercap")]
+            memory_energy: None,
+            #[cfg(feature = "enrichment-powercap")]
+            io_energy: None,
+            #[cfg(feature = "enrichment-powercap")]
+            network_energy: None,
+            #[cfg(feature = "enrichment-powercap")]
+            cpu_energy_total: None,
+            #[cfg(feature = "enrichment-powercap")]
+            dynamic_energy: None,
+            #[cfg(feature = "enrichment-powercap")]
+            static_energy: None,
+            #[cfg(feature = "enrichment-powercap")]
+            power_watts: None,
+            #[cfg(feature = "enrichment-powercap")]
+            unattributed_energy: None,
+            #[cfg(feature = "enrichment-powercap")]
+            energy_zones: Vec::new(),
+            #[cfg(feature = "enrichment-powercap")]
+            energy_anomaly: None,
+            #[cfg(feature = "enrichment-powercap")]
+            energy_method: None,
+            #[cfg(feature = "enrichment-sensors")]
+            sensor_energy: None,
+            #[cfg(feature = "enrichment-carbon")]
+            co2_grams: None,
+            #[cfg(feature = "enrichment-carbon")]
+            co2_grams_total: None,
+            #[cfg(feature = "enrichment-cost")]
+            cost: None,
+            #[cfg(feature = "enrichment-cost")]
+            cost_total: None,
+            #[cfg(feature = "enrichment-cost")]
+            cost_currency: None,
+            network: None,
+            network_rates: None,
+            networks: Vec::new(),
+            block_io: None,
+            block_io_rates: None,
+            block_devices: Vec::new(),
+        }
+    }
+
+    /// Record of a lifecycle event of a container.
+    pub fn event(
+        container_id: String,
+        container_name: String,
+        ts: DateTime<Utc>,
+        event: ContainerEvent,
+    ) -> Self {
+        Self {
+            record_type: RecordType::Event,
+            event: Some(event),
+            ..Self::new(container_id, container_name, ts)
+        }
+    }
+
+    /// Record of the totals of a container, when it stops being watched.
+    pub fn summary(container_id: String, container_name: String, ts: DateTime<Utc>) -> Self {
+        Self {
+            record_type: RecordType::Summary,
+            ..Self::new(container_id, container_name, ts)
+        }
+    }
+
+    /// Energy attributed to the container, measured or estimated, for the cpu,
+    /// the memory, the disks and the network, in micro joules.
+    #[cfg(feature = "enrichment-powercap")]
+    pub fn attributed_energy(&self) -> Option<f64> {
+        [
+            self.cpu_energy,
+            self.dram_energy,
+            self.memory_energy,
+            self.io_energy,
+            self.network_energy,
+        ]
+        .iter()
+        .flatten()
+        .fold(None, |total, energy| {
+            Some(total.unwrap_or_default() + energy)
+        })
+    }
+
+    pub fn counters(&self) -> Counters {
+        Counters {
+            ts: self.ts,
+            network: self.network,
+            networks: self
+                .networks
+                .iter()
+                .map(|iface| (iface.name.clone(), iface.usage))
+                .collect(),
+            block_io: self.block_io,
+            block_devices: self
+                .block_devices
+                .iter()
+                .map(|device| (device.name.clone(), device.usage))
+                .collect(),
+        }
+    }
+
+    /// Computes the per-second rates since the counters of the previous record.
+    pub fn with_rates(mut self, previous: Option<&Counters>) -> Self {
+        let previous = match previous {
+            Some(previous) => previous,
+            None => return self,
+        };
+        let seconds = (self.ts - previous.ts).num_milliseconds() as f64 / 1_000.0;
+        if seconds <= 0.0 {
+            return self;
+        }
+        self.network_rates = match (self.network, previous.network) {
+            (Some(current), Some(previous)) => current.rates(&previous, seconds),
+            _ => None,
+        };
+        for iface in self.networks.iter_mut() {
+            iface.rates = previous
+                .networks
+                .get(&iface.name)
+                .and_then(|previous| iface.usage.rates(previous, seconds));
+        }
+        self.block_io_rates = match (self.block_io, previous.block_io) {
+            (Some(current), Some(previous)) => current.rates(&previous, seconds),
+            _ => None,
+        };
+        for device in self.block_devices.iter_mut() {
+            device.rates = previous
+                .block_devices
+                .get(&device.name)
+                .and_then(|previous| device.usage.rates(previous, seconds));
         }
         self
     }
+
+    /// Time of the field, for the fields representing a point in time.
+    pub fn timestamp(&self, field: Field) -> Option<DateTime<Utc>> {
+        match field {
+            Field::Ts => Some(self.ts),
+            Field::CreatedAt => self.metadata.as_ref().and_then(|meta| meta.created_at),
+            Field::StartedAt => self.metadata.as_ref().and_then(|meta| meta.started_at),
+            _ => None,
+        }
+    }
+
+    fn metadata_text<F>(&self, getter: F) -> Value<'_>
+    where
+        F: for<'m> Fn(&'m ContainerMetadata) -> Option<&'m str>,
+    {
+        self.metadata
+            .as_deref()
+            .and_then(getter)
+            .map(Value::from)
+            .unwrap_or(Value::Null)
+    }
+
+    pub fn get(&self, field: Field) -> Value<'_> {
+        match field {
+            Field::RecordType => Value::from(self.record_type.name()),
+            Field::Event => self
+                .event
+                .as_ref()
+                .map(|event| Value::from(event.action.as_str()))
+                .unwrap_or(Value::Null),
+            Field::ExitCode => self
+                .event
+                .as_ref()
+                .and_then(|event| event.exit_code)
+                .map(Value::Integer)
+                .unwrap_or(Value::Null),
+            Field::Signal => self
+                .event
+                .as_ref()
+                .and_then(|event| event.signal.as_deref())
+                .map(Value::from)
+                .unwrap_or(Value::Null),
+            Field::ContainerId => Value::from(self.container_id.as_str()),
+            Field::ContainerName => Value::from(self.container_name.as_str()),
+            Field::ProcessId => Value::from(self.process_id),
+            Field::ProcessCommand => self
+                .process_command
+                .as_deref()
+                .map(Value::from)
+                .unwrap_or(Value::Null),
+            Field::Image => self.metadata_text(|meta| meta.image.as_deref()),
+            Field::ImageId => self.metadata_text(|meta| meta.image_id.as_deref()),
+            Field::ComposeProject => self.metadata_text(ContainerMetadata::compose_project),
+            Field::ComposeService => self.metadata_text(ContainerMetadata::compose_service),
+            Field::Hostname => self.metadata_text(|meta| meta.hostname.as_deref()),
+            Field::RestartPolicy => self.metadata_text(|meta| meta.restart_policy.as_deref()),
+            Field::CreatedAt | Field::StartedAt => self
+                .timestamp(field)
+                .map(|ts| Value::Integer(ts.timestamp()))
+                .unwrap_or(Value::Null),
+            Field::HealthStatus => self
+                .health
+                .as_deref()
+                .and_then(|health| health.status.as_deref())
+                .map(Value::from)
+                .unwrap_or(Value::Null),
+            Field::HealthFailingStreak => Value::from(
+                self.health
+                    .as_deref()
+                    .and_then(|health| health.failing_streak),
+            ),
+            Field::RestartCount => Value::from(
+                self.health
+                    .as_deref()
+                    .and_then(|health| health.restart_count),
+            ),
+            Field::OomKilled => self
+                .health
+                .as_deref()
+                .and_then(|health| health.oom_killed)
+                .map(Value::Boolean)
+                .unwrap_or(Value::Null),
+            Field::Labels => match self.metadata {
+                Some(ref meta) => Value::Map(
+                    meta.labels
+                        .iter()
+                        .map(|(key, value)| {
+                            (Cow::Borrowed(key.as_str()), Value::from(value.as_str()))
+                        })
+                        .collect(),
+                ),
+                None => Value::Null,
+            },
+            Field::Ts => Value::Integer(self.ts.timestamp()),
+            Field::PidCount => Value::from(self.pid_count),
+            Field::PidLimit => Value::from(self.pid_limit),
+            Field::MemoryUsage => Value::from(self.memory_usage),
+            Field::MemoryLimit => Value::from(self.memory_limit),
+            Field::MemoryCache => Value::from(self.memory_cache),
+            Field::MemoryRss => Value::from(self.memory_rss),
+            Field::MemoryInactiveFile => Value::from(self.memory_inactive_file),
+            Field::MemoryWorkingSet => Value::from(self.memory_working_set),
+            Field::MemorySwap => Value::from(self.memory_swap),
+            Field::MemoryPercent => Value::from(self.memory_percent),
+            Field::SampleInterval => Value::from(self.sample_interval),
+            Field::CpuPercent => Value::from(self.cpu_percent),
+            Field::CpuDockerPercent => Value::from(self.cpu_docker_percent),
+            Field::CpuCount => Value::Unsigned(self.cpu_count),
+            Field::CpuTime => Value::from(self.cpu_time),
+            Field::CpuUserTime => Value::from(self.cpu_user_time),
+            Field::CpuKernelTime => Value::from(self.cpu_kernel_time),
+            Field::CpuThrottlingPeriods => Value::from(self.cpu_throttling_periods),
+            Field::CpuThrottledPeriods => Value::from(self.cpu_throttled_periods),
+            Field::CpuThrottledTime => Value::from(self.cpu_throttled_time),
+            Field::CpuPerCore => match self.cpu_per_core {
+                Some(ref cores) => {
+                    Value::List(cores.iter().copied().map(Value::Unsigned).collect())
+                }
+                None => Value::Null,
+            },
+            Field::Load1 => Value::from(self.load_average.map(|load| load[0])),
+            Field::Load5 => Value::from(self.load_average.map(|load| load[1])),
+            Field::Load15 => Value::from(self.load_average.map(|load| load[2])),
+            #[cfg(feature = "enrichment-powercap")]
+            Field::CpuEnergy => Value::from(self.cpu_energy),
+            #[cfg(feature = "enrichment-powercap")]
+            Field::DramEnergy => Value::from(self.dram_energy),
+            #[cfg(feature = "enrichment-powercap")]
+            Field::MemoryEnergy => Value::from(self.memory_energy),
+            #[cfg(feature = "enrichment-powercap")]
+            Field::IoEnergy => Value::from(self.io_energy),
+            #[cfg(feature = "enrichment-powercap")]
+            Field::NetworkEnergy => Value::from(self.network_energy),
+            #[cfg(feature = "enrichment-powercap")]
+            Field::CpuEnergyTotal => Value::from(self.cpu_energy_total),
+            #[cfg(feature = "enrichment-powercap")]
+            Field::DynamicEnergy => Value::from(self.dynamic_energy),
+            #[cfg(feature = "enrichment-powercap")]
+            Field::StaticEnergy => Value::from(self.static_energy),
+            #[cfg(feature = "enrichment-powercap")]
+            Field::PowerWatts => Value::from(self.power_watts),
+            #[cfg(feature = "enrichment-powercap")]
+            Field::UnattributedEnergy => Value::from(self.unattributed_energy),
+            #[cfg(feature = "enrichment-powercap")]
+            Field::EnergyZones => Value::Map(
+                self.energy_zones
+                    .iter()
+                    .map(|zone| (Cow::Borrowed(zone.name.as_str()), Value::Float(zone.energy)))
+                    .collect(),
+            ),
+            #[cfg(feature = "enrichment-powercap")]
+            Field::EnergyAnomaly => self
+                .energy_anomaly
+                .map(|anomaly| Value::from(anomaly.name()))
+                .unwrap_or(Value::Null),
+            #[cfg(feature = "enrichment-powercap")]
+            Field::EnergyMethod => self
+                .energy_method
+                .map(|method| Value::from(method.name()))
+                .unwrap_or(Value::Null),
+            #[cfg(feature = "enrichment-sensors")]
+            Field::SensorEnergy => Value::from(self.sensor_energy),
+            #[cfg(feature = "enrichment-carbon")]
+            Field::Co2Grams => Value::from(self.co2_grams),
+            #[cfg(feature = "enrichment-carbon")]
+            Field::Co2GramsTotal => Value::from(self.co2_grams_total),
+            #[cfg(feature = "enrichment-cost")]
+            Field::Cost => Value::from(self.cost),
+            #[cfg(feature = "enrichment-cost")]
+            Field::CostTotal => Value::from(self.cost_total),
+            #[cfg(feature = "enrichment-cost")]
+            Field::CostCurrency => self
+                .cost_currency
+                .as_deref()
+                .map(Value::from)
+                .unwrap_or(Value::Null),
+            Field::NetworkRxBytes => Value::from(self.network.map(|n| n.rx_bytes)),
+            Field::NetworkRxPackets => Value::from(self.network.map(|n| n.rx_packets)),
+            Field::NetworkRxErrors => Value::from(self.network.map(|n| n.rx_errors)),
+            Field::NetworkRxDropped => Value::from(self.network.map(|n| n.rx_dropped)),
+            Field::NetworkTxBytes => Value::from(self.network.map(|n| n.tx_bytes)),
+            Field::NetworkTxPackets => Value::from(self.network.map(|n| n.tx_packets)),
+            Field::NetworkTxErrors => Value::from(self.network.map(|n| n.tx_errors)),
+            Field::NetworkTxDropped => Value::from(self.network.map(|n| n.tx_dropped)),
+            Field::NetworkRxBytesRate => Value::from(self.network_rates.map(|r| r.rx_bytes)),
+            Field::NetworkRxPacketsRate => Value::from(self.network_rates.map(|r| r.rx_packets)),
+            Field::NetworkTxBytesRate => Value::from(self.network_rates.map(|r| r.tx_bytes)),
+            Field::NetworkTxPacketsRate => Value::from(self.network_rates.map(|r| r.tx_packets)),
+            Field::Networks => Value::Map(
+                self.networks
+                    .iter()
+                    .map(|iface| (Cow::Borrowed(iface.name.as_str()), iface.value()))
+                    .collect(),
+            ),
+            Field::BlockReadBytes => Value::from(self.block_io.map(|b| b.read_bytes)),
+            Field::BlockWriteBytes => Value::from(self.block_io.map(|b| b.write_bytes)),
+            Field::BlockReadOps => Value::from(self.block_io.and_then(|b| b.read_ops)),
+            Field::BlockWriteOps => Value::from(self.block_io.and_then(|b| b.write_ops)),
+            Field::BlockReadBytesRate => Value::from(self.block_io_rates.map(|r| r.read_bytes)),
+            Field::BlockWriteBytesRate => Value::from(self.block_io_rates.map(|r| r.write_bytes)),
+            Field::BlockReadOpsRate => Value::from(self.block_io_rates.and_then(|r| r.read_ops)),
+            Field::BlockWriteOpsRate => Value::from(self.block_io_rates.and_then(|r| r.write_ops)),
+            Field::BlockDevices => Value::Map(
+                self.block_devices
+                    .iter()
+                    .map(|device| (Cow::Borrowed(device.name.as_str()), device.value()))
+                    .collect(),
+            ),
+        }
+    }
+}
+
+/// Field of a record that can be exported.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Field {
+    RecordType,
+    Event,
+    ExitCode,
+    Signal,
+    ContainerId,
+    ContainerName,
+    ProcessId,
+    ProcessCommand,
+    Image,
+    ImageId,
+    ComposeProject,
+    ComposeService,
+    Hostname,
+    RestartPolicy,
+    CreatedAt,
+    StartedAt,
+    Labels,
+    HealthStatus,
+    HealthFailingStreak,
+    RestartCount,
+    OomKilled,
+    Ts,
+    PidCount,
+    PidLimit,
+    MemoryUsage,
+    MemoryLimit,
+    MemoryCache,
+    MemoryRss,
+    MemoryInactiveFile,
+    MemoryWorkingSet,
+    MemorySwap,
+    MemoryPercent,
+    SampleInterval,
+    CpuPercent,
+    CpuDockerPercent,
+    CpuCount,
+    CpuTime,
+    CpuUserTime,
+    CpuKernelTime,
+    CpuThrottlingPeriods,
+    CpuThrottledPeriods,
+    CpuThrottledTime,
+    CpuPerCore,
+    Load1,
+    Load5,
+    Load15,
+    #[cfg(feature = "enrichment-powercap")]
+    CpuEnergy,
+    #[cfg(feature = "enrichment-powercap")]
+    DramEnergy,
+    #[cfg(feature = "enrichment-powercap")]
+    MemoryEnergy,
+    #[cfg(feature = "enrichment-powercap")]
+    IoEnergy,
+    #[cfg(feature = "enrichment-powercap")]
+    NetworkEnergy,
+    #[cfg(feature = "enrichment-powercap")]
+    CpuEnergyTotal,
+    #[cfg(feature = "enrichment-powercap")]
+    DynamicEnergy,
+    #[cfg(feature = "enrichment-powercap")]
+    StaticEnergy,
+    #[cfg(feature = "enrichment-powercap")]
+    PowerWatts,
+    #[cfg(feature = "enrichment-powercap")]
+    UnattributedEnergy,
+    #[cfg(feature = "enrichment-powercap")]
+    EnergyZones,
+    #[cfg(feature = "enrichment-powercap")]
+    EnergyAnomaly,
+    #[cfg(feature = "enrichment-powercap")]
+    EnergyMethod,
+    #[cfg(feature = "enrichment-sensors")]
+    SensorEnergy,
+    #[cfg(feature = "enrichment-carbon")]
+    Co2Grams,
+    #[cfg(feature = "enrichment-carbon")]
+    Co2GramsTotal,
+    #[cfg(feature = "enrichment-cost")]
+    Cost,
+    #[cfg(feature = "enrichment-cost")]
+    CostTotal,
+    #[cfg(feature = "enrichment-cost")]
+    CostCurrency,
+    NetworkRxBytes,
+    NetworkRxPackets,
+    NetworkRxErrors,
+    NetworkRxDropped,
+    NetworkTxBytes,
+    NetworkTxPackets,
+    NetworkTxErrors,
+    NetworkTxDropped,
+    NetworkRxBytesRate,
+    NetworkRxPacketsRate,
+    NetworkTxBytesRate,
+    NetworkTxPacketsRate,
+    Networks,
+    BlockReadBytes,
+    BlockWriteBytes,
+    BlockReadOps,
+    BlockWriteOps,
+    BlockReadBytesRate,
+    BlockWriteBytesRate,
+    BlockReadOpsRate,
+    BlockWriteOpsRate,
+    BlockDevices,
+}
+
+impl Field {
+    /// Every available field, in the default output order, the new ones
+    /// being added at the end so that the columns of the CSV output stay in place.
+    pub const ALL: &'static [Field] = &[
+        Self::ContainerId,
+        Self::ContainerName,
+        Self::Ts,
+        Self::PidCount,
+        Self::PidLimit,
+        Self::MemoryUsage,
+        Self::MemoryLimit,
+        Self::CpuPercent,
+        Self::CpuCount,
+        #[cfg(feature = "enrichment-powercap")]
+        Self::CpuEnergy,
+        Self::NetworkRxBytes,
+        Self::NetworkRxPackets,
+        Self::NetworkRxErrors,
+        Self::NetworkRxDropped,
+        Self::NetworkTxBytes,
+        Self::NetworkTxPackets,
+        Self::NetworkTxErrors,
+        Self::NetworkTxDropped,
+        Self::NetworkRxBytesRate,
+        Self::NetworkRxPacketsRate,
+        Self::NetworkTxBytesRate,
+        Self::NetworkTxPacketsRate,
+        Self::Networks,
+        Self::BlockReadBytes,
+        Self::BlockWriteBytes,
+        Self::BlockReadOps,
+        Self::BlockWriteOps,
+        Self::BlockReadBytesRate,
+        Self::BlockWriteBytesRate,
+        Self::BlockReadOpsRate,
+        Self::BlockWriteOpsRate,
+        Self::BlockDevices,
+        Self::MemoryCache,
+        Self::MemoryRss,
+        Self::MemoryInactiveFile,
+        Self::MemoryWorkingSet,
+        Self::MemoryPercent,
+        Self::CpuUserTime,
+        Self::CpuKernelTime,
+        Self::CpuThrottlingPeriods,
+        Self::CpuThrottledPeriods,
+        Self::CpuThrottledTime,
+        Self::CpuPerCore,
+        Self::SampleInterval,
+        Self::CpuDockerPercent,
+        Self::Image,
+        Self::ImageId,
+        Self::ComposeProject,
+        Self::ComposeService,
+        Self::Hostname,
+        Self::RestartPolicy,
+        Self::CreatedAt,
+        Self::StartedAt,
+        Self::Labels,
+        Self::RecordType,
+        Self::Event,
+        Self::ExitCode,
+        Self::Signal,
+        Self::HealthStatus,
+        Self::HealthFailingStreak,
+        Self::RestartCount,
+        Self::OomKilled,
+        Self::Load1,
+        Self::Load5,
+        Self::Load15,
+        #[cfg(feature = "enrichment-powercap")]
+        Self::UnattributedEnergy,
+        Self::ProcessId,
+        Self::ProcessCommand,
+        #[cfg(feature = "enrichment-powercap")]
+        Self::DramEnergy,
+        #[cfg(feature = "enrichment-powercap")]
+        Self::EnergyZones,
+        #[cfg(feature = "enrichment-powercap")]
+        Self::EnergyAnomaly,
+        Self::CpuTime,
+        #[cfg(feature = "enrichment-powercap")]
+        Self::CpuEnergyTotal,
+        #[cfg(feature = "enrichment-powercap")]
+        Self::PowerWatts,
+        #[cfg(feature = "enrichment-carbon")]
+        Self::Co2Grams,
+        #[cfg(feature = "enrichment-carbon")]
+        Self::Co2GramsTotal,
+        #[cfg(feature = "enrichment-cost")]
+        Self::Cost,
+        #[cfg(feature = "enrichment-cost")]
+        Self::CostTotal,
+        #[cfg(feature = "enrichment-cost")]
+        Self::CostCurrency,
+        #[cfg(feature = "enrichment-powercap")]
+        Self::EnergyMethod,
+        #[cfg(feature = "enrichment-powercap")]
+        Self::MemoryEnergy,
+        #[cfg(feature = "enrichment-powercap")]
+        Self::IoEnergy,
+        #[cfg(feature = "enrichment-powercap")]
+        Self::NetworkEnergy,
+        #[cfg(feature = "enrichment-sensors")]
+        Self::SensorEnergy,
+        #[cfg(feature = "enrichment-powercap")]
+        Self::DynamicEnergy,
+        #[cfg(feature = "enrichment-powercap")]
+        Self::StaticEnergy,
+        Self::MemorySwap,
+    ];
+
+    /// Default name of the field in the output.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::RecordType => "recordType",
+            Self::Event => "event",
+            Self::ExitCode => "exitCode",
+            Self::Signal => "signal",
+            Self::ContainerId => "containerId",
+            Self::ContainerName => "containerName",
+            Self::ProcessId => "processId",
+            Self::ProcessCommand => "processCommand",
+            Self::Image => "image",
+            Self::ImageId => "imageId",
+            Self::ComposeProject => "composeProject",
+            Self::ComposeService => "composeService",
+            Self::Hostname => "hostname",
+            Self::RestartPolicy => "restartPolicy",
+            Self::CreatedAt => "createdAt",
+            Self::StartedAt => "startedAt",
+            Self::Labels => "labels",
+            Self::HealthStatus => "healthStatus",
+            Self::HealthFailingStreak => "healthFailingStreak",
+            Self::RestartCount => "restartCount",
+            Self::OomKilled => "oomKilled",
+            Self::Ts => "ts",
+            Self::PidCount => "pidCount",
+            Self::PidLimit => "pidLimit",
+            Self::MemoryUsage => "memoryUsage",
+            Self::MemoryLimit => "memoryLimit",
+            Self::MemoryCache => "memoryCache",
+            Self::MemoryRss => "memoryRss",
+            Self::MemoryInactiveFile => "memoryInactiveFile",
+            Self::MemoryWorkingSet => "memoryWorkingSet",
+            Self::MemorySwap => "memorySwap",
+            Self::MemoryPercent => "memoryPercent",
+            Self::SampleInterval => "sampleInterval",
+            Self::CpuPercent => "cpuPercent",
+            Self::CpuDockerPercent => "cpuDockerPercent",
+            Self::CpuCount => "cpuCount",
+            Self::CpuTime => "cpuTime",
+            Self::CpuUserTime => "cpuUserTime",
+            Self::CpuKernelTime => "cpuKernelTime",
+            Self::CpuThrottlingPeriods => "cpuThrottlingPeriods",
+            Self::CpuThrottledPeriods => "cpuThrottledPeriods",
+            Self::CpuThrottledTime => "cpuThrottledTime",
+            Self::CpuPerCore => "cpuPerCore",
+            Self::Load1 => "load1",
+            Self::Load5 => "load5",
+            Self::Load15 => "load15",
+            #[cfg(feature = "enrichment-powercap")]
+            Self::CpuEnergy => "cpuEnergy",
+            #[cfg(feature = "enrichment-powercap")]
+            Self::DramEnergy => "dramEnergy",
+            #[cfg(feature = "enrichment-powercap")]
+            Self::MemoryEnergy => "memoryEnergy",
+            #[cfg(feature = "enrichment-powercap")]
+            Self::IoEnergy => "ioEnergy",
+            #[cfg(feature = "enrichment-powercap")]
+            Self::NetworkEnergy => "networkEnergy",
+            #[cfg(feature = "enrichment-powercap")]
+            Self::CpuEnergyTotal => "cpuEnergyTotal",
+            #[cfg(feature = "enrichment-powercap")]
+            Self::DynamicEnergy => "dynamicEnergy",
+            #[cfg(feature = "enrichment-powercap")]
+            Self::StaticEnergy => "staticEnergy",
+            #[cfg(feature = "enrichment-powercap")]
+            Self::PowerWatts => "powerWatts",
+            #[cfg(feature = "enrichment-powercap")]
+            Self::UnattributedEnergy => "unattributedEnergy",
+            #[cfg(feature = "enrichment-powercap")]
+            Self::EnergyZones => "energyZones",
+            #[cfg(feature = "enrichment-powercap")]
+            Self::EnergyAnomaly => "energyAnomaly",
+            #[cfg(feature = "enrichment-powercap")]
+            Self::EnergyMethod => "energyMethod",
+            #[cfg(feature = "enrichment-sensors")]
+            Self::SensorEnergy => "sensorEnergy",
+            #[cfg(feature = "enrichment-carbon")]
+            Self::Co2Grams => "co2Grams",
+            #[cfg(feature = "enrichment-carbon")]
+            Self::Co2GramsTotal => "co2GramsTotal",
+            #[cfg(feature = "enrichment-cost")]
+            Self::Cost => "cost",
+            #[cfg(feature = "enrichment-cost")]
+            Self::CostTotal => "costTotal",
+            #[cfg(feature = "enrichment-cost")]
+            Self::CostCurrency => "costCurrency",
+            Self::NetworkRxBytes => "networkRxBytes",
+            Self::NetworkRxPackets => "networkRxPackets",
+            Self::NetworkRxErrors => "networkRxErrors",
+            Self::NetworkRxDropped => "networkRxDropped",
+            Self::NetworkTxBytes => "networkTxBytes",
+            Self::NetworkTxPackets => "networkTxPackets",
+            Self::NetworkTxErrors => "networkTxErrors",
+            Self::NetworkTxDropped => "networkTxDropped",
+            Self::NetworkRxBytesRate => "networkRxBytesRate",
+            Self::NetworkRxPacketsRate => "networkRxPacketsRate",
+            Self::NetworkTxBytesRate => "networkTxBytesRate",
+            Self::NetworkTxPacketsRate => "networkTxPacketsRate",
+            Self::Networks => "networks",
+            Self::BlockReadBytes => "blockReadBytes",
+            Self::BlockWriteBytes => "blockWriteBytes",
+            Self::BlockReadOps => "blockReadOps",
+            Self::BlockWriteOps => "blockWriteOps",
+            Self::BlockReadBytesRate => "blockReadBytesRate",
+            Self::BlockWriteBytesRate => "blockWriteBytesRate",
+            Self::BlockReadOpsRate => "blockReadOpsRate",
+            Self::BlockWriteOpsRate => "blockWriteOpsRate",
+            Self::BlockDevices => "blockDevices",
+        }
+    }
+
+    /// Physical quantity of the field, when it can be exported in different units.
+    pub fn quantity(&self) -> Option<Quantity> {
+        match self {
+            Self::Ts | Self::CreatedAt | Self::StartedAt => Some(Quantity::Time),
+            Self::MemoryUsage
+            | Self::MemoryLimit
+            | Self::MemoryCache
+            | Self::MemoryRss
+            | Self::MemoryInactiveFile
+            | Self::MemoryWorkingSet
+            | Self::MemorySwap => Some(Quantity::Memory),
+            Self::CpuPercent => Some(Quantity::Cpu),
+            #[cfg(feature = "enrichment-powercap")]
+            Self::CpuEnergy
+            | Self::DramEnergy
+            | Self::MemoryEnergy
+            | Self::IoEnergy
+            | Self::NetworkEnergy
+            | Self::CpuEnergyTotal
+            | Self::DynamicEnergy
+            | Self::StaticEnergy
+            | Self::UnattributedEnergy
+            | Self::EnergyZones => Some(Quantity::Energy),
+            #[cfg(feature = "enrichment-sensors")]
+            Self::SensorEnergy => Some(Quantity::Energy),
+            _ => None,
+        }
+    }
+
+    /// Type of the values of the field.
+    #[cfg(feature = "formatter-avro")]
+    pub fn kind(&self) -> Kind {
+        match self {
+            Self::RecordType
+            | Self::Event
+            | Self::Signal
+            | Self::ContainerId
+            | Self::ContainerName
+            | Self::ProcessCommand
+            | Self::Image
+            | Self::ImageId
+            | Self::ComposeProject
+            | Self::ComposeService
+            | Self::Hostname
+            | Self::RestartPolicy
+            | Self::HealthStatus => Kind::Text,
+            Self::OomKilled => Kind::Boolean,
+            Self::Labels => Kind::Map(&Kind::Text),
+            Self::SampleInterval
+            | Self::CpuPercent
+            | Self::CpuDockerPercent
+            | Self::MemoryPercent
+            | Self::Load1
+            | Self::Load5
+            | Self::Load15 => Kind::Float,
+            #[cfg(feature = "enrichment-powercap")]
+            Self::CpuEnergy
+            | Self::DramEnergy
+            | Self::MemoryEnergy
+            | Self::IoEnergy
+            | Self::NetworkEnergy
+            | Self::CpuEnergyTotal
+            | Self::DynamicEnergy
+            | Self::StaticEnergy
+            | Self::PowerWatts
+            | Self::UnattributedEnergy => Kind::Float,
+            #[cfg(feature = "enrichment-powercap")]
+            Self::EnergyZones => Kind::Map(&Kind::Float),
+            #[cfg(feature = "enrichment-powercap")]
+            Self::EnergyAnomaly | Self::EnergyMethod => Kind::Text,
+            #[cfg(feature = "enrichment-sensors")]
+            Self::SensorEnergy => Kind::Float,
+            #[cfg(feature = "enrichment-carbon")]
+            Self::Co2Grams | Self::Co2GramsTotal => Kind::Float,
+            #[cfg(feature = "enrichment-cost")]
+            Self::Cost | Self::CostTotal => Kind::Float,
+            #[cfg(feature = "enrichment-cost")]
+            Self::CostCurrency => Kind::Text,
+            Self::NetworkRxBytesRate
+            | Self::NetworkRxPacketsRate
+            | Self::NetworkTxBytesRate
+            | Self::NetworkTxPacketsRate
+            | Self::BlockReadBytesRate
+            | Self::BlockWriteBytesRate
+            | Self::BlockReadOpsRate
+            | Self::BlockWriteOpsRate => Kind::Float,
+            Self::CpuPerCore => Kind::List(&Kind::Integer),
+            Self::Networks => Kind::Map(&NETWORK_INTERFACE),
+            Self::BlockDevices => Kind::Map(&BLOCK_DEVICE),
+            _ => Kind::Integer,
+        }
+    }
+
+    /// Whether the field can be missing from a record.
+    #[cfg(feature = "formatter-avro")]
+    pub fn nullable(&self) -> bool {
+        !matches!(
+            self,
+            Self::RecordType
+                | Self::ContainerId
+                | Self::ContainerName
+                | Self::Ts
+                | Self::CpuCount
+                | Self::Networks
+                | Self::BlockDevices
+        )
+    }
+
+    /// Cargo feature providing the field, if it's not always available.
+    #[cfg(feature = "formatter-avro")]
+    pub fn feature(&self) -> Option<&'static str> {
+        match self {
+            #[cfg(feature = "enrichment-powercap")]
+            Self::CpuEnergy
+            | Self::DramEnergy
+            | Self::MemoryEnergy
+            | Self::IoEnergy
+            | Self::NetworkEnergy
+            | Self::CpuEnergyTotal
+            | Self::DynamicEnergy
+            | Self::StaticEnergy
+            | Self::PowerWatts
+            | Self::UnattributedEnergy
+            | Self::EnergyZones
+            | Self::EnergyAnomaly
+            | Self::EnergyMethod => Some("enrichment-powercap"),
+            #[cfg(feature = "enrichment-sensors")]
+            Self::SensorEnergy => Some("enrichment-sensors"),
+            #[cfg(feature = "enrichment-carbon")]
+            Self::Co2Grams | Self::Co2GramsTotal => Some("enrichment-carbon"),
+            #[cfg(feature = "enrichment-cost")]
+            Self::Cost | Self::CostTotal | Self::CostCurrency => Some("enrichment-cost"),
+            _ => None,
+        }
+    }
+}
+
+/// Physical quantity represented by a field.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Quantity {
+    Cpu,
+    #[cfg(feature = "enrichment-powercap")]
+    Energy,
+    Memory,
+    Time,
+}
+
+/// Type of the values of a field.
+#[cfg(feature = "formatter-avro")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    Boolean,
+    Integer,
+    Float,
+    Text,
+    /// Value that can be missing, inside a nested record.
+    Nullable(&'static Kind),
+    /// List of values of the same type.
+    List(&'static Kind),
+    /// Map from text keys to values of the same type.
+    Map(&'static Kind),
+    /// Nested record, with its name and the name and type of its fields.
+    Record(&'static str, &'static [(&'static str, Kind)]),
+}
+
+impl FromStr for Field {
+    type Err = String;
+
+    /// Parses a field from its output name, either in camel case or in snake case.
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let normalized = input.replace('_', "").to_lowercase();
+        Self::ALL
+            .iter()
+            .find(|field| field.name().to_lowercase() == normalized)
+            .copied()
+            .ok_or_else(|| format!("unknown field {:?}", input))
+    }
+}
+
+/// Value of a record field, independent of the output format.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value<'a> {
+    Null,
+    Boolean(bool),
+    Integer(i64),
+    Unsigned(u64),
+    Float(f64),
+    Text(Cow<'a, str>),
+    List(Vec<Value<'a>>),
+    /// Ordered entries of a map or of a nested record.
+    Map(Vec<(Cow<'a, str>, Value<'a>)>),
+}
+
+impl<'a> From<&'a str> for Value<'a> {
+    fn from(value: &'a str) -> Self {
+        Self::Text(Cow::Borrowed(value))
+    }
+}
+
+impl From<Option<u64>> for Value<'_> {
+    fn from(value: Option<u64>) -> Self {
+        value.map(Self::Unsigned).unwrap_or(Self::Null)
+    }
+}
+
+impl From<Option<f64>> for Value<'_> {
+    fn from(value: Option<f64>) -> Self {
+        value.map(Self::Float).unwrap_or(Self::Null)
+    }
+}
+
+#[cfg(feature = "formatter-json")]
+impl serde::Serialize for Value<'_> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Self::Null => serializer.serialize_none(),
+            Self::Boolean(value) => serializer.serialize_bool(*value),
+            Self::Integer(value) => serializer.serialize_i64(*value),
+            Self::Unsigned(value) => serializer.serialize_u64(*value),
+            Self::Float(value) => serializer.serialize_f64(*value),
+            Self::Text(value) => serializer.serialize_str(value),
+            Self::List(items) => serializer.collect_seq(items),
+            Self::Map(entries) => {
+                use serde::ser::SerializeMap;
+
+                let mut map = serializer.serialize_map(Some(entries.len()))?;
+                for (key, value) in entries {
+                    map.serialize_entry(key, value)?;
+                }
+                map.end()
+            }
+        }
+    }
+}
+
+#[cfg(feature = "formatter-json")]
+impl serde::Serialize for Record {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeMap;
+
+        let mut map = serializer.serialize_map(Some(Field::ALL.len()))?;
+        for field in Field::ALL {
+            map.serialize_entry(field.name(), &self.get(*field))?;
+        }
+        map.end()
+    }
 }
 
 #[cfg(test)]
 impl Record {
-    #[cfg(feature = "formatter-json")]
     pub fn random() -> Self {
+        use chrono::TimeZone;
+
         Self {
+            record_type: RecordType::Sample,
+            event: None,
             container_id: "hello".into(),
             container_name: "world".into(),
-            ts: 1234,
+            process_id: None,
+            process_command: None,
+            metadata: None,
+            health: None,
+            ts: Utc.timestamp_opt(1234, 0).unwrap(),
             pid_count: Some(12),
             pid_limit: Some(20),
             memory_usage: Some(14),
             memory_limit: None,
+            memory_cache: Some(4),
+            memory_rss: Some(10),
+            memory_inactive_file: Some(2),
+            memory_working_set: Some(12),
+            memory_swap: Some(0),
+            memory_percent: None,
             cpu_count: 2,
-            cpu_percent: 0.89,
+            sample_interval: Some(1.0),
+            cpu_percent: Some(0.89),
+            cpu_docker_percent: Some(178.0),
+            cpu_time: Some(800),
+            cpu_user_time: Some(600),
+            cpu_kernel_time: Some(200),
+            cpu_throttling_periods: None,
+            cpu_throttled_periods: None,
+            cpu_throttled_time: None,
+            cpu_per_core: None,
+            load_average: None,
             #[cfg(feature = "enrichment-powercap")]
             cpu_energy: Some(0.23),
+            #[cfg(feature = "enrichment-powercap")]
+            dram_energy: None,
+            #[cfg(feature = "enrichment-powercap")]
+            memory_energy: None,
+            #[cfg(feature = "enrichment-powercap")]
+            io_energy: None,
+            #[cfg(feature = "enrichment-powercap")]
+            network_energy: None,
+            #[cfg(feature = "enrichment-powercap")]
+            cpu_energy_total: Some(1.23),
+            #[cfg(feature = "enrichment-powercap")]
+            dynamic_energy: None,
+            #[cfg(feature = "enrichment-powercap")]
+            static_energy: None,
+            #[cfg(feature = "enrichment-powercap")]
+            power_watts: None,
+            #[cfg(feature = "enrichment-powercap")]
+            unattributed_energy: None,
+            #[cfg(feature = "enrichment-powercap")]
+            energy_zones: Vec::new(),
+            #[cfg(feature = "enrichment-powercap")]
+            energy_anomaly: None,
+            #[cfg(feature = "enrichment-powercap")]
+            energy_method: None,
+            #[cfg(feature = "enrichment-sensors")]
+            sensor_energy: None,
+            #[cfg(feature = "enrichment-carbon")]
+            co2_grams: None,
+            #[cfg(feature = "enrichment-carbon")]
+            co2_grams_total: None,
+            #[cfg(feature = "enrichment-cost")]
+            cost: None,
+            #[cfg(feature = "enrichment-cost")]
+            cost_total: None,
+            #[cfg(feature = "enrichment-cost")]
+            cost_currency: None,
+            network: Some(NetworkUsage {
+                rx_bytes: 1024,
+                tx_bytes: 512,
+                ..Default::default()
+            }),
+            network_rates: None,
+            networks: Vec::new(),
+            block_io: Some(BlockUsage {
+                read_bytes: 4096,
+                write_bytes: 8192,
+                read_ops: None,
+                write_ops: None,
+            }),
+            block_io_rates: None,
+            block_devices: Vec::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CpuDelta;
+    use super::{BlockDevice, BlockUsage, Field, NetworkInterface, NetworkUsage, Record};
+    use bollard::container::{BlkioStats, BlkioStatsEntry, CPUStats, CPUUsage, ThrottlingData};
+    use chrono::Duration;
+    use std::str::FromStr;
+
+    fn with_interface(mut record: Record, name: &str, rx_bytes: u64) -> Record {
+        record.networks.push(NetworkInterface {
+            name: name.into(),
+            usage: NetworkUsage {
+                rx_bytes,
+                ..Default::default()
+            },
+            rates: None,
+        });
+        record
+    }
+
+    #[test]
+    fn should_compute_network_rates() {
+        let first = with_interface(Record::random(), "eth0", 1000);
+        let first = with_interface(first, "eth1", 1000);
+        let mut second = with_interface(Record::random(), "eth0", 3000);
+        second = with_interface(second, "eth1", 10);
+        second.ts = first.ts + Duration::seconds(2);
+        second.network = Some(NetworkUsage {
+            rx_bytes: 2024,
+            tx_bytes: 1512,
+            ..Default::default()
+        });
+        let second = second.with_rates(Some(&first.counters()));
+        let rates = second.network_rates.unwrap();
+        assert_eq!(rates.rx_bytes, 500.0);
+        assert_eq!(rates.tx_bytes, 500.0);
+        assert_eq!(second.networks[0].rates.unwrap().rx_bytes, 1000.0);
+        // the counters of a recreated interface went backwards
+        assert_eq!(second.networks[1].rates, None);
+    }
+
+    #[test]
+    fn should_sum_block_io_per_device() {
+        let entry = |minor, op: &str, value| BlkioStatsEntry {
+            major: 8,
+            minor,
+            op: op.into(),
+            value,
+        };
+        let stats = BlkioStats {
+            io_service_bytes_recursive: Some(vec![
+                entry(16, "Read", 10),
+                entry(0, "Read", 20),
+                entry(0, "Write", 30),
+                entry(0, "Total", 50),
+            ]),
+            io_serviced_recursive: Some(vec![entry(0, "Read", 2)]),
+            io_queue_recursive: None,
+            io_service_time_recursive: None,
+            io_wait_time_recursive: None,
+            io_merged_recursive: None,
+            io_time_recursive: None,
+            sectors_recursive: None,
+        };
+        let devices = BlockDevice::collect(&stats);
+        assert_eq!(devices.len(), 2);
+        assert_eq!(devices[0].name, "8:0");
+        assert_eq!(
+            devices[0].usage,
+            BlockUsage {
+                read_bytes: 20,
+                write_bytes: 30,
+                read_ops: Some(2),
+                write_ops: None,
+            }
+        );
+        assert_eq!(devices[1].usage.read_ops, None);
+        let total = devices[0].usage + devices[1].usage;
+        assert_eq!(total.read_bytes, 30);
+        assert_eq!(total.read_ops, Some(2));
+    }
+
+    #[test]
+    fn should_compute_block_io_rates() {
+        let first = Record::random();
+        let mut second = Record::random();
+        second.ts = first.ts + Duration::milliseconds(500);
+        second.block_io = Some(BlockUsage {
+            read_bytes: 4096,
+            write_bytes: 9192,
+            read_ops: None,
+            write_ops: None,
+        });
+        let rates = second
+            .with_rates(Some(&first.counters()))
+            .block_io_rates
+            .unwrap();
+        assert_eq!(rates.read_bytes, 0.0);
+        assert_eq!(rates.write_bytes, 2000.0);
+        assert_eq!(rates.read_ops, None);
+    }
+
+    fn cpu_stats(system: u64, user: u64, throttled: u64, cores: Vec<u64>) -> CPUStats {
+        CPUStats {
+            cpu_usage: CPUUsage {
+                percpu_usage: Some(cores),
+                usage_in_usermode: user,
+                total_usage: user,
+                usage_in_kernelmode: 0,
+            },
+            system_cpu_usage: Some(system),
+            online_cpus: Some(2),
+            throttling_data: ThrottlingData {
+                periods: 0,
+                throttled_periods: 0,
+                throttled_time: throttled,
+            },
         }
     }
+
+    #[test]
+    fn should_compute_cpu_deltas() {
+        let previous = cpu_stats(1000, 100, 10, vec![60, 40]);
+        let current = cpu_stats(2000, 300, 15, vec![210, 90]);
+        let delta = CpuDelta::new(&current, &previous);
+        assert_eq!(
+            delta.delta(|stats| stats.cpu_usage.usage_in_usermode),
+            Some(200)
+        );
+        assert_eq!(
+            delta.delta(|stats| stats.throttling_data.throttled_time),
+            Some(5)
+        );
+        assert_eq!(delta.per_core(), Some(vec![150, 50]));
+        // a core went offline
+        let current = cpu_stats(2000, 300, 15, vec![210]);
+        assert_eq!(CpuDelta::new(&current, &previous).per_core(), None);
+    }
+
+    #[test]
+    fn should_compute_host_share() {
+        let previous = cpu_stats(1000, 100, 10, vec![60, 40]);
+        let current = cpu_stats(2000, 300, 15, vec![210, 90]);
+        let delta = CpuDelta::new(&current, &previous);
+        assert_eq!(delta.host_share(None), Some(0.2));
+        // without the cpu time of the host, the interval is used
+        let mut previous = previous;
+        let mut current = current;
+        previous.system_cpu_usage = None;
+        current.system_cpu_usage = None;
+        let delta = CpuDelta::new(&current, &previous);
+        assert_eq!(delta.host_share(None), None);
+        assert_eq!(
+            delta.host_share(Some(Duration::nanoseconds(500))),
+            Some(0.2)
+        );
+    }
+
+    #[test]
+    fn should_not_compute_cpu_deltas_on_first_sample() {
+        let previous = cpu_stats(0, 0, 0, Vec::new());
+        let current = cpu_stats(2000, 300, 15, vec![210, 90]);
+        let delta = CpuDelta::new(&current, &previous);
+        assert_eq!(delta.delta(|stats| stats.cpu_usage.usage_in_usermode), None);
+        assert_eq!(delta.per_core(), None);
+        assert_eq!(delta.host_share(Some(Duration::seconds(1))), None);
+    }
+
+    #[test]
+    fn working_set_should_exclude_inactive_cache() {
+        assert_eq!(super::working_set(Some(100), Some(30)), Some(70));
+        assert_eq!(super::working_set(Some(100), Some(130)), Some(100));
+        assert_eq!(super::working_set(Some(100), None), Some(100));
+        assert_eq!(super::working_set(None, Some(30)), None);
+    }
+
+    #[test]
+    #[cfg(feature = "serde_json")]
+    fn should_read_memory_details_of_both_cgroup_versions() {
+        use bollard::container::MemoryStatsStats;
+
+        // every key set to zero but the given ones
+        let stats = |keys: &str, values: &[(&str, u64)]| -> MemoryStatsStats {
+            let mut stats: serde_json::Map<_, _> = keys
+                .split_whitespace()
+                .map(|key| (key.to_string(), serde_json::json!(0)))
+                .collect();
+            for (key, value) in values {
+                stats.insert(key.to_string(), serde_json::json!(value));
+            }
+            serde_json::from_value(stats.into()).unwrap()
+        };
+        let v1 = stats(
+            "cache dirty mapped_file total_inactive_file pgpgout rss total_mapped_file writeback
+            unevictable pgpgin total_unevictable pgmajfault total_rss total_rss_huge
+            total_writeback total_inactive_anon rss_huge hierarchical_memory_limit total_pgfault
+            total_active_file active_anon total_active_anon total_pgpgout total_cache total_dirty
+            inactive_anon active_file pgfault inactive_file total_pgmajfault total_pgpgin",
+            &[
+                ("total_cache", 40),
+                ("total_rss", 60),
+                ("total_inactive_file", 30),
+            ],
+        );
+        assert!(matches!(v1, MemoryStatsStats::V1(_)));
+        assert_eq!(
+            super::memory_details(Some(&v1)),
+            (Some(40), Some(60), Some(30), None)
+        );
+        let v2 = stats(
+            "anon file kernel_stack slab sock shmem file_mapped file_dirty file_writeback anon_thp
+            inactive_anon active_anon inactive_file active_file unevictable slab_reclaimable
+            slab_unreclaimable pgfault pgmajfault workingset_refault workingset_activate
+            workingset_nodereclaim pgrefill pgscan pgsteal pgactivate pgdeactivate pglazyfree
+            pglazyfreed thp_fault_alloc thp_collapse_alloc",
+            &[("file", 20), ("anon", 70), ("inactive_file", 15)],
+        );
+        assert!(matches!(v2, MemoryStatsStats::V2(_)));
+        assert_eq!(
+            super::memory_details(Some(&v2)),
+            (Some(20), Some(70), Some(15), None)
+        );
+        assert_eq!(super::memory_details(None), (None, None, None, None));
+    }
+
+    #[test]
+    fn should_not_compute_rates_without_previous_record() {
+        let record = Record::random().with_rates(None);
+        assert_eq!(record.network_rates, None);
+    }
+
+    #[test]
+    fn field_should_parse_any_case() {
+        assert_eq!(Field::from_str("pidLimit").unwrap(), Field::PidLimit);
+        assert_eq!(Field::from_str("pid_limit").unwrap(), Field::PidLimit);
+        assert!(Field::from_str("whatever").is_err());
+    }
+
+    #[test]
+    fn fields_should_start_with_the_original_columns() {
+        let names: Vec<_> = Field::ALL.iter().take(9).map(Field::name).collect();
+        assert_eq!(
+            names,
+            [
+                "containerId",
+                "containerName",
+                "ts",
+                "pidCount",
+                "pidLimit",
+                "memoryUsage",
+                "memoryLimit",
+                "cpuPercent",
+                "cpuCount"
+            ]
+        );
+    }
 }
@@ -0,0 +1,225 @@
+use crate::model::{Record, RecordType};
+use bollard::models::ContainerTopResponse;
+use std::collections::HashMap;
+
+const KIBIBYTE: u64 = 1024;
+
+/// Process running in a container, as listed by `docker top`, with its usage read
+/// from `/proc`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProcessUsage {
+    /// Process id in the pid namespace of the host.
+    pub pid: u64,
+    pub command: String,
+    /// Time spent in user and kernel mode since the start of the process, in clock ticks.
+    pub cpu_ticks: Option<u64>,
+    /// Resident memory, in bytes.
+    pub memory_rss: Option<u64>,
+}
+
+impl ProcessUsage {
+    /// Lists the processes of the response of `docker top`, whatever the `ps` arguments.
+    pub fn list(response: &ContainerTopResponse) -> Vec<Self> {
+        let titles = match response.titles {
+            Some(ref titles) => titles,
+            None => return Vec::new(),
+        };
+        let column = |names: &[&str]| titles.iter().position(|title| names.contains(&&**title));
+        let (pid, command) = match (column(&["PID"]), column(&["CMD", "COMMAND"])) {
+            (Some(pid), Some(command)) => (pid, command),
+            _ => return Vec::new(),
+        };
+        response
+            .processes
+            .iter()
+            .flatten()
+            .filter_map(|process| {
+                Some(Self {
+                    pid: process.get(pid)?.parse().ok()?,
+                    command: process.get(command)?.clone(),
+                    cpu_ticks: None,
+                    memory_rss: None,
+                })
+            })
+            .collect()
+    }
+
+    /// Reads the usage of the process, which is only possible when sharing the pid
+    /// namespace of the host.
+    pub fn read(mut self) -> Self {
+        self.cpu_ticks = std::fs::read_to_string(format!("/proc/{}/stat", self.pid))
+            .ok()
+            .as_deref()
+            .and_then(parse_cpu_ticks);
+        self.memory_rss = std::fs::read_to_string(format!("/proc/{}/status", self.pid))
+            .ok()
+            .as_deref()
+            .and_then(parse_memory_rss);
+        self
+    }
+}
+
+/// Reads `utime` and `stime` from `/proc/<pid>/stat`.
+fn parse_cpu_ticks(input: &str) -> Option<u64> {
+    // the command, between parentheses, may contain spaces
+    let (_, fields) = input.rsplit_once(')')?;
+    let mut fields = fields.split_whitespace().skip(11);
+    let user: u64 = fields.next()?.parse().ok()?;
+    let kernel: u64 = fields.next()?.parse().ok()?;
+    Some(user + kernel)
+}
+
+/// Reads `VmRSS` from `/proc/<pid>/status`, missing for kernel threads.
+fn parse_memory_rss(input: &str) -> Option<u64> {
+    input
+        .lines()
+        .find_map(|line| line.strip_prefix("VmRSS:"))
+        .and_then(|rest| rest.split_whitespace().next())
+        .and_then(|value| value.parse::<u64>().ok())
+        .map(|value| value * KIBIBYTE)
+}
+
+/// Splits the usage of a container between its processes.
+#[derive(Default)]
+pub struct ProcessSampler {
+    /// Cpu ticks of each process at the previous sample.
+    previous: HashMap<u64, u64>,
+}
+
+impl ProcessSampler {
+    /// Builds a record for each process, with the share of the cpu and energy of
+    /// the container proportional to the cpu time of the process since the previous
+    /// sample.
+    pub fn records(&mut self, container: &Record, processes: Vec<ProcessUsage>) -> Vec<Record> {
+        let deltas: Vec<Option<u64>> = processes
+            .iter()
+            .map(|process| {
+                let previous = self.previous.get(&process.pid)?;
+                process.cpu_ticks?.checked_sub(*previous)
+            })
+            .collect();
+        let total: u64 = deltas.iter().flatten().sum();
+        self.previous = processes
+            .iter()
+            .filter_map(|process| Some((process.pid, process.cpu_ticks?)))
+            .collect();
+        processes
+            .into_iter()
+            .zip(deltas)
+            .map(|(process, delta)| {
+                let ratio = delta.map(|delta| match total {
+                    0 => 0.0,
+                    _ => delta as f64 / total as f64,
+                });
+                let share = |value: Option<f64>| value.zip(ratio).map(|(v, r)| v * r);
+                let mut record = Record::new(
+                    container.container_id.clone(),
+                    container.container_name.clone(),
+                    container.ts,
+                );
+                record.record_type = RecordType::Process;
+                record.process_id = Some(process.pid);
+                record.process_command = Some(process.command);
+                record.metadata = container.metadata.clone();
+                record.health = container.health.clone();
+                record.sample_interval = container.sample_interval;
+                record.cpu_count = container.cpu_count;
+                record.cpu_percent = share(container.cpu_percent);
+                record.cpu_docker_percent = share(container.cpu_docker_percent);
+                record.memory_usage = process.memory_rss;
+                #[cfg(feature = "enrichment-powercap")]
+                {
+                    record.cpu_energy = share(container.cpu_energy);
+                }
+                record
+            })
+            .collect()
+    }
+
+    pub fn reset(&mut self) {
+        self.previous.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_cpu_ticks, parse_memory_rss, ProcessSampler, ProcessUsage};
+    use crate::model::{Record, RecordType};
+    use bollard::models::ContainerTopResponse;
+
+    fn process(pid: u64, cpu_ticks: u64) -> ProcessUsage {
+        ProcessUsage {
+            pid,
+            command: format!("worker {}", pid),
+            cpu_ticks: Some(cpu_ticks),
+            memory_rss: Some(4096),
+        }
+    }
+
+    #[test]
+    fn should_list_processes() {
+        let response = ContainerTopResponse {
+            titles: Some(
+                ["UID", "PID", "PPID", "C", "STIME", "TTY", "TIME", "CMD"]
+                    .iter()
+                    .map(|title| title.to_string())
+                    .collect(),
+            ),
+            processes: Some(vec![
+                [
+                    "root",
+                    "1234",
+                    "1",
+                    "0",
+                    "10:00",
+                    "?",
+                    "00:00:01",
+                    "nginx: master",
+                ]
+                .iter()
+                .map(|value| value.to_string())
+                .collect(),
+                vec!["invalid".into()],
+            ]),
+        };
+        let processes = ProcessUsage::list(&response);
+        assert_eq!(processes.len(), 1);
+        assert_eq!(processes[0].pid, 1234);
+        assert_eq!(processes[0].command, "nginx: master");
+        assert!(ProcessUsage::list(&ContainerTopResponse::default()).is_empty());
+    }
+
+    #[test]
+    fn should_parse_proc_files() {
+        let stat = "42 (my (weird) cmd) S 1 42 42 0 -1 4194560 100 0 0 0 120 30 0 0 20 0 1 0";
+        assert_eq!(parse_cpu_ticks(stat), Some(150));
+        assert_eq!(parse_cpu_ticks("42 (cmd) S 1"), None);
+        let status = "Name:\tnginx\nVmPeak:\t  2048 kB\nVmRSS:\t  1024 kB\n";
+        assert_eq!(parse_memory_rss(status), Some(1024 * 1024));
+        assert_eq!(parse_memory_rss("Name:\tkthreadd\n"), None);
+    }
+
+    #[test]
+    fn should_split_the_container_usage() {
+        let mut sampler = ProcessSampler::default();
+        let container = Record::random();
+        let records = sampler.records(&container, vec![process(1, 100), process(2, 50)]);
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].record_type, RecordType::Process);
+        assert_eq!(records[0].process_id, Some(1));
+        assert_eq!(records[0].container_name, "world");
+        assert_eq!(records[0].memory_usage, Some(4096));
+        // without previous sample, the shares are unknown
+        assert_eq!(records[0].cpu_percent, None);
+
+        let records = sampler.records(
+            &container,
+            vec![process(1, 130), process(2, 60), process(3, 500)],
+        );
+        assert_eq!(records[0].cpu_percent, Some(0.89 * 0.75));
+        assert_eq!(records[1].cpu_percent, Some(0.89 * 0.25));
+        assert_eq!(records[2].cpu_percent, None);
+        #[cfg(feature = "enrichment-powercap")]
+        assert_eq!(records[0].cpu_energy, Some(0.23 * 0.75));
+    }
+}
@@ -1,38 +1,62 @@
 use crate::enrichment::{Enricher, EnrichmentBuilder, EnrichmentStack};
 use crate::error::Error;
-use crate::model::Record;
+use crate::host::HostSampler;
+use crate::model::{ContainerEvent, Counters, Message, Record};
+use crate::process::{ProcessSampler, ProcessUsage};
 use crate::Params;
-use bollard::container::{ListContainersOptions, StatsOptions};
+use bollard::container::{ListContainersOptions, StatsOptions, TopOptions};
 use bollard::models::EventMessage;
 use bollard::system::EventsOptions;
 use bollard::Docker;
+use chrono::{TimeZone, Utc};
 use futures_util::stream::StreamExt;
 use std::collections::{HashMap, HashSet};
 use std::convert::TryFrom;
 use std::sync::{Arc, Mutex};
-use tokio::sync::mpsc;
+use std::time::Duration;
+use tokio::sync::{mpsc, watch};
 use tracing::{debug, info, trace, warn};
 
+/// Watchers currently running, by container name, with the channel forwarding
+/// the docker events of their container.
+type Register = Arc<Mutex<HashMap<String, mpsc::UnboundedSender<EventMessage>>>>;
+
 struct ContainerWatcher {
     docker: Arc<Docker>,
     enrichers: EnrichmentStack,
+    events: mpsc::UnboundedReceiver<EventMessage>,
     name: String,
+    /// Counters of the last record, to compute the rates of the next one.
+    previous: Option<Counters>,
+    /// Name of the container when it was last inspected.
+    inspected: Option<String>,
+    processes: Option<ProcessSampler>,
+    /// Id of the container, known after its first sample.
+    container_id: Option<String>,
+    /// Set when shutting down, to stop watching after sending the summary.
+    stopping: watch::Receiver<bool>,
 }
 
 impl ContainerWatcher {
-    async fn execute(
+    fn new(
         docker: Arc<Docker>,
         enrichers: EnrichmentStack,
-        register: Arc<Mutex<HashSet<String>>>,
+        events: mpsc::UnboundedReceiver<EventMessage>,
         name: String,
-        tx: mpsc::Sender<Record>,
-    ) -> Result<(), Error> {
-        let mut watcher = ContainerWatcher {
+        processes: bool,
+        stopping: watch::Receiver<bool>,
+    ) -> Self {
+        ContainerWatcher {
             docker,
             enrichers,
+            events,
             name,
-        };
-        watcher.run(register, tx).await
+            previous: None,
+            inspected: None,
+            processes: processes.then(ProcessSampler::default),
+            container_id: None,
+            stopping,
+        }
     }
 
     async fn is_alive(&mut self) -> Result<bool, Error> {
@@ -55,13 +79,59 @@ impl ContainerWatcher {
             .map_err(|err| Error::Custom(format!("couldn't list containers: {:?}", err)))
     }
 
-    async fn run(
-        &mut self,
-        register: Arc<Mutex<HashSet<String>>>,
-        tx: mpsc::Sender<Record>,
-    ) -> Result<(), Error> {
+    /// Inspects the container for the enrichers, when it's new, has been renamed or
+    /// when an enricher asked for it.
+    async fn inspect(&mut self, record: &Record) {
+        if !self.enrichers.needs_inspect()
+            || self.inspected.as_deref() == Some(record.container_name.as_str())
+        {
+            return;
+        }
+        debug!("inspecting container {:?}", record.container_name);
+        match self
+            .docker
+            .inspect_container(&record.container_id, None)
+            .await
+        {
+            Ok(container) => self.enrichers.inspect(&container),
+            Err(err) => warn!("unable to inspect container {:?}: {:?}", self.name, err),
+        }
+        self.inspected = Some(record.container_name.clone());
+    }
+
+    /// Splits the usage of the container between its processes, when enabled.
+    async fn sample_processes(&mut self, record: &Record) -> Vec<Record> {
+        let sampler = match self.processes {
+            Some(ref mut sampler) => sampler,
+            None => return Vec::new(),
+        };
+        let options: Option<TopOptions<String>> = None;
+        match self
+            .docker
+            .top_processes(&record.container_id, options)
+            .await
+        {
+            Ok(response) => {
+                let processes = ProcessUsage::list(&response)
+                    .into_iter()
+                    .map(ProcessUsage::read)
+                    .collect();
+                sampler.records(record, processes)
+            }
+            Err(err) => {
+                warn!("unable to list processes of {:?}: {:?}", self.name, err);
+                Vec::new()
+            }
+        }
+    }
+
+    async fn run(&mut self, register: Register, tx: mpsc::Sender<Message>) -> Result<(), Error> {
         info!("watching container {:?}", self.name);
-        while self.is_alive().await? {
+        let container_name = self.name.trim_start_matches('/').to_string();
+        if let Err(err) = tx.send(Message::Started(container_name.clone())).await {
+            warn!("unable to forward start: {:?}", err);
+        }
+        while !*self.stopping.borrow() && self.is_alive().await? {
             let stream = &mut self.docker.stats(
                 self.name.trim_start_matches('/'),
                 Some(StatsOptions {
@@ -70,19 +140,56 @@ impl ContainerWatcher {
                 }),
             );
             debug!("starting the watch of {:?}", self.name);
-            while let Some(Ok(stat)) = stream.next().await {
-                let snap = Record::from(stat);
-                let snap = self.enrichers.enrich(snap);
-                if let Err(err) = tx.send(snap).await {
-                    warn!("unable to forward snapshot: {:?}", err);
+            loop {
+                tokio::select! {
+                    stat = stream.next() => {
+                        let stat = match stat {
+                            Some(Ok(stat)) => stat,
+                            _ => break,
+                        };
+                        let snap = Record::from(stat).with_rates(self.previous.as_ref());
+                        self.previous = Some(snap.counters());
+                        self.container_id = Some(snap.container_id.clone());
+                        self.inspect(&snap).await;
+                        let snap = self.enrichers.enrich(snap);
+                        let processes = self.sample_processes(&snap).await;
+                        for record in std::iter::once(snap).chain(processes) {
+                            if let Err(err) = tx.send(Message::Record(Box::new(record))).await {
+                                warn!("unable to forward snapshot: {:?}", err);
+                            }
+                        }
+                    }
+                    Some(event) = self.events.recv() => {
+                        if self.enrichers.notify(&event) {
+                            self.inspected = None;
+                        }
+                    }
+                    _ = self.stopping.changed() => break,
                 }
             }
             debug!("lost connection with stats for container {:?}", self.name);
+            self.enrichers.reset();
+            self.previous = None;
+            if let Some(ref mut sampler) = self.processes {
+                sampler.reset();
+            }
+            // the container may have been restarted in between
+            self.inspected = None;
+        }
+        if let Some(container_id) = self.container_id.take() {
+            let summary = Record::summary(container_id, container_name.clone(), Utc::now());
+            let summary = self.enrichers.finish(summary);
+            if let Err(err) = tx.send(Message::Record(Box::new(summary))).await {
+                warn!("unable to forward summary: {:?}", err);
+            }
+        }
+        if let Err(err) = tx.send(Message::Stopped(container_name)).await {
+            warn!("unable to forward stop: {:?}", err);
         }
 
         let mut lock = register.try_lock();
         if let Ok(ref mut mutex) = lock {
-            mutex.remove(&self.name);
+            mutex.remove(self.name.trim_start_matches('/'));
             info!("done watching container {:?}", self.name);
         } else {
             warn!("couldn't unregister container {:?}", self.name);
@@ -91,6 +198,39 @@ impl ContainerWatcher {
     }
 }
 
+/// Builds the record of a lifecycle event, when it's one of the exported actions.
+fn get_event_record(container_name: &str, event: &EventMessage) -> Option<Record> {
+    let action = event.action.as_deref()?;
+    if !ContainerEvent::ACTIONS.contains(&action) {
+        return None;
+    }
+    let actor = event.actor.as_ref();
+    let attribute = |key: &str| {
+        actor
+            .and_then(|actor| actor.attributes.as_ref())
+            .and_then(|attrs| attrs.get(key))
+    };
+    let ts = event
+        .time_nano
+        .map(|nanos| Utc.timestamp_nanos(nanos))
+        .or_else(|| {
+            event
+                .time
+                .and_then(|secs| Utc.timestamp_opt(secs, 0).single())
+        })
+        .unwrap_or_else(Utc::now);
+    Some(Record::event(
+        actor.and_then(|actor| actor.id.clone()).unwrap_or_default(),
+        container_name.trim_start_matches('/').to_string(),
+        ts,
+        ContainerEvent {
+            action: action.to_string(),
+            exit_code: attribute("exitCode").and_then(|code| code.parse().ok()),
+            signal: attribute("signal").cloned(),
+        },
+    ))
+}
+
 fn get_container_name(event: &EventMessage) -> Option<String> {
     event
         .actor
@@ -110,7 +250,12 @@ pub struct Orchestrator {
     docker: Arc<Docker>,
     enrichment: Arc<EnrichmentBuilder>,
     names: HashSet<String>,
-    tasks: Arc<Mutex<HashSet<String>>>,
+    tasks: Register,
+    processes: bool,
+    /// Sampler of the whole host, taken when starting.
+    host: Option<HostSampler>,
+    /// Set when shutting down, once started.
+    stopping: Option<watch::Receiver<bool>>,
 }
 
 impl TryFrom<Params> for Orchestrator {
@@ -118,14 +263,23 @@ impl TryFrom<Params> for Orchestrator {
 
     fn try_from(value: Params) -> Result<Self, Self::Error> {
         let docker = Arc::new(value.create_docker()?);
+        let enrichment = value.enrichment_builder();
+        let host = HostSampler::new(
+            Duration::from_secs(value.host_interval.max(1)),
+            &enrichment,
+            !value.disable_host,
+        );
         Ok(Self {
             docker,
-            enrichment: Arc::new(value.enrichment_builder()),
+            enrichment: Arc::new(enrichment),
             names: value
                 .containers
                 .map(|value| value.split(',').map(String::from).collect())
                 .unwrap_or_default(),
-            tasks: Arc::new(Mutex::new(HashSet::new())),
+            tasks: Arc::new(Mutex::new(HashMap::new())),
+            processes: value.processes,
+            host,
+            stopping: None,
         })
     }
 }
@@ -134,51 +288,78 @@ impl Orchestrator {
     fn is_running(&self, name: &str) -> bool {
         self.tasks
             .lock()
-            .map(|lock| lock.contains(name))
+            .map(|lock| lock.contains_key(name.trim_start_matches('/')))
             .unwrap_or(false)
     }
 
-    fn register_task(&self, name: String) {
+    fn register_task(&self, name: &str) -> mpsc::UnboundedReceiver<EventMessage> {
+        let (sender, receiver) = mpsc::unbounded_channel();
         self.tasks
             .lock()
-            .map(|mut lock| lock.insert(name))
+            .map(|mut lock| lock.insert(name.trim_start_matches('/').to_string(), sender))
             .expect("mutex is corrupted");
+        receiver
+    }
+
+    /// Forwards the event to the watcher of the container, if any.
+    fn forward_event(&self, name: &str, event: &EventMessage) {
+        if let Ok(lock) = self.tasks.lock() {
+            if let Some(sender) = lock.get(name.trim_start_matches('/')) {
+                if sender.send(event.clone()).is_err() {
+                    debug!("watcher of container {:?} is already stopped", name);
+                }
+            }
+        }
     }
 
     fn handle_start_event(
         &mut self,
         container_name: String,
-        tx: mpsc::Sender<Record>,
+        tx: mpsc::Sender<Message>,
     ) -> Result<(), Error> {
         if self.is_running(&container_name) {
             debug!("container {:?} already running", container_name);
             return Ok(());
         }
-        self.register_task(container_name.clone());
+        let events = self.register_task(&container_name);
         let docker = self.docker.clone();
         let enrichment = self.enrichment.clone();
         let tasks = self.tasks.clone();
+        let processes = self.processes;
+        let stopping = self.stopping.clone().expect("orchestrator not started");
         tokio::spawn(async move {
             let enricher = enrichment.build();
-            if let Err(err) =
-                ContainerWatcher::execute(docker, enricher, tasks, container_name, tx).await
-            {
+            let mut watcher = ContainerWatcher::new(
+                docker,
+                enricher,
+                events,
+                container_name,
+                processes,
+                stopping,
+            );
+            if let Err(err) = watcher.run(tasks, tx).await {
                 warn!("container watcher errored: {:?}", err);
             }
         });
         Ok(())
     }
 
-    fn handle_event(
+    async fn handle_event(
         &mut self,
         container_name: String,
-        action: Option<String>,
-        tx: mpsc::Sender<Record>,
+        event: &EventMessage,
+        tx: mpsc::Sender<Message>,
     ) -> Result<(), Error> {
         if !self.names.is_empty() && !self.names.contains(&container_name) {
             return Ok(());
         }
-        match action.as_deref() {
+        self.forward_event(&container_name, event);
+        if let Some(record) = get_event_record(&container_name, event) {
+            tx.send(Message::Record(Box::new(record)))
+                .await
+                .map_err(|err| Error::Custom(format!("couldn't forward event: {:?}", err)))?;
+        }
+        match event.action.as_deref() {
             Some("start") => self.handle_start_event(container_name, tx),
             _ => Ok(()),
         }
@@ -203,7 +384,15 @@ impl Orchestrator {
             .map_err(|err| Error::Custom(format!("couldn't list running containers: {:?}", err)))
     }
 
-    pub async fn run(&mut self, tx: mpsc::Sender<Record>) -> Result<(), Error> {
+    pub async fn run(
+        &mut self,
+        tx: mpsc::Sender<Message>,
+        mut stopping: watch::Receiver<bool>,
+    ) -> Result<(), Error> {
+        self.stopping = Some(stopping.clone());
+        if let Some(host) = self.host.take() {
+            tokio::spawn(host.run(tx.clone(), stopping.clone()));
+        }
         for name in self.list_running().await? {
             self.handle_start_event(name, tx.clone())?;
         }
@@ -214,13 +403,21 @@ impl Orchestrator {
             until: None,
             filters,
         }));
-        while let Some(Ok(event)) = stream.next().await {
+        loop {
+            let event = tokio::select! {
+                event = stream.next() => match event {
+                    Some(Ok(event)) => event,
+                    _ => break,
+                },
+                // the watchers stop by themselves
+                _ = stopping.changed() => break,
+            };
             if let Some(container_name) = get_container_name(&event) {
                 debug!(
                     "received action {:?} for container {:?}",
                     event.action, container_name
                 );
-                if let Err(err) = self.handle_event(container_name, event.action, tx.clone()) {
+                if let Err(err) = self.handle_event(container_name, &event, tx.clone()).await {
                     warn!("couldn't handle event: {:?}", err);
                 }
             }
@@ -228,3 +425,50 @@ impl Orchestrator {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::get_event_record;
+    use crate::model::{Field, RecordType, Value};
+    use bollard::models::{EventActor, EventMessage};
+    use std::collections::HashMap;
+
+    fn event(action: &str, attributes: &[(&str, &str)]) -> EventMessage {
+        EventMessage {
+            action: Some(action.into()),
+            actor: Some(EventActor {
+                id: Some("abcd".into()),
+                attributes: Some(
+                    attributes
+                        .iter()
+                        .map(|(key, value)| (key.to_string(), value.to_string()))
+                        .collect::<HashMap<_, _>>(),
+                ),
+            }),
+            time: Some(1234),
+            time_nano: Some(1_234_000_000_500),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn should_build_event_records() {
+        let record = get_event_record("/world", &event("die", &[("exitCode", "137")])).unwrap();
+        assert_eq!(record.record_type, RecordType::Event);
+        assert_eq!(record.container_id, "abcd");
+        assert_eq!(record.container_name, "world");
+        assert_eq!(record.ts.timestamp_nanos_opt(), Some(1_234_000_000_500));
+        assert_eq!(record.get(Field::Event), Value::from("die"));
+        assert_eq!(record.get(Field::ExitCode), Value::Integer(137));
+        assert_eq!(record.get(Field::Signal), Value::Null);
+
+        let record = get_event_record("world", &event("kill", &[("signal", "9")])).unwrap();
+        assert_eq!(record.get(Field::Signal), Value::from("9"));
+    }
+
+    #[test]
+    fn should_ignore_other_actions() {
+        assert!(get_event_record("world", &event("exec_start: sh", &[])).is_none());
+        assert!(get_event_record("world", &event("create", &[])).is_none());
+    }
+}